@@ -0,0 +1,6 @@
+INSERT INTO users (user_id, username, password_hash)
+VALUES (
+    'ddf8994f-d522-4659-8d02-c1d479057be6',
+    'admin',
+    '$argon2id$v=19$m=15000,t=2,p=1$gDz2XNmERo0TKS6eOxEHXQ$y45VHNX/9wBW9v5Zkjj/8SQezhAh3KV4GM3lx38ftVg'
+);
@@ -0,0 +1,5 @@
+CREATE TABLE sessions (
+    id text PRIMARY KEY,
+    data bytea NOT NULL,
+    expiry_date timestamptz NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE subscription_tokens
+ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+ADD COLUMN expires_at TIMESTAMPTZ NOT NULL DEFAULT now() + INTERVAL '24 hours',
+ADD COLUMN consumed_at TIMESTAMPTZ NULL;
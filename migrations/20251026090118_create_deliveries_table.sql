@@ -0,0 +1,15 @@
+CREATE TABLE deliveries (
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- One of `queued`, `sent`, `failed` or `bounced`.
+    status TEXT NOT NULL,
+    n_attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    provider_message_id TEXT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX deliveries_provider_message_id_idx ON deliveries (provider_message_id);
@@ -0,0 +1,8 @@
+CREATE TABLE email_events (
+    email_event_id uuid PRIMARY KEY,
+    record_type TEXT NOT NULL,
+    email TEXT NULL,
+    provider_message_id TEXT NULL,
+    payload jsonb NOT NULL,
+    received_at timestamptz NOT NULL
+);
@@ -0,0 +1,21 @@
+BEGIN;
+
+ALTER TABLE newsletter_issues
+ADD COLUMN author_id uuid NULL REFERENCES users (user_id),
+ADD COLUMN created_at timestamptz NULL,
+ADD COLUMN slug TEXT NULL;
+
+-- Issues published before authors were tracked keep a NULL author.
+UPDATE newsletter_issues
+SET
+    created_at = published_at,
+    slug = coalesce(
+        nullif(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+        'issue'
+    ) || '-' || left(newsletter_issue_id::text, 8);
+
+ALTER TABLE newsletter_issues ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
+
+COMMIT;
@@ -0,0 +1,18 @@
+BEGIN;
+
+-- One of `draft`, `scheduled` or `published`.
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN send_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NULL;
+
+UPDATE newsletter_issues SET status = 'published', updated_at = published_at;
+
+ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN updated_at SET NOT NULL;
+-- Drafts and scheduled issues have not been published yet.
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (send_at)
+WHERE status = 'scheduled';
+
+COMMIT;
@@ -0,0 +1,32 @@
+BEGIN;
+
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The links in an issue, in document order. Click tracking only ever
+-- redirects to one of these.
+CREATE TABLE issue_links (
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    link_index INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, link_index)
+);
+
+CREATE TABLE tracking_events (
+    tracking_event_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL,
+    subscriber_id uuid NOT NULL,
+    -- One of `open` or `click`.
+    kind TEXT NOT NULL,
+    -- The link that was clicked, for clicks.
+    link_index INTEGER NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    FOREIGN KEY (newsletter_issue_id, subscriber_id)
+    REFERENCES deliveries (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX tracking_events_delivery_idx
+ON tracking_events (newsletter_issue_id, subscriber_id);
+
+COMMIT;
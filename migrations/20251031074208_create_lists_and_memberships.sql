@@ -0,0 +1,49 @@
+BEGIN;
+
+CREATE TABLE lists (
+    list_id uuid PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Everyone who subscribed so far subscribed to this one.
+INSERT INTO lists (list_id, slug, name)
+VALUES ('7f2e0c52-52a3-4c1e-9a5b-3c6f0d0b9e41', 'newsletter', 'Newsletter');
+
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- One of `pending_confirmation`, `confirmed` or `unsubscribed`. Bounces
+    -- and complaints concern the address, and stay on `subscriptions`.
+    status TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+INSERT INTO list_memberships (list_id, subscriber_id, status)
+SELECT
+    '7f2e0c52-52a3-4c1e-9a5b-3c6f0d0b9e41',
+    id,
+    CASE WHEN status IN ('bounced', 'complained') THEN 'unsubscribed' ELSE status END
+FROM subscriptions;
+
+-- Confirmation is per list.
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens SET list_id = '7f2e0c52-52a3-4c1e-9a5b-3c6f0d0b9e41';
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+-- The lists an issue goes out to.
+CREATE TABLE issue_lists (
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+INSERT INTO issue_lists (newsletter_issue_id, list_id)
+SELECT newsletter_issue_id, '7f2e0c52-52a3-4c1e-9a5b-3c6f0d0b9e41'
+FROM newsletter_issues;
+
+COMMIT;
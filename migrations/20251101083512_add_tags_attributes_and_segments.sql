@@ -0,0 +1,16 @@
+BEGIN;
+
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+
+-- A segment expression narrowing down who, on the issue's lists, gets it.
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
+
+COMMIT;
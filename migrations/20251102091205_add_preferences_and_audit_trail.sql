@@ -0,0 +1,20 @@
+BEGIN;
+
+-- One of `immediately`, `daily` or `weekly`.
+ALTER TABLE subscriptions ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediately';
+
+-- Every change a subscriber makes to their own subscription, kept for
+-- compliance. `field` is `name`, `digest_frequency`, `status` or
+-- `list:<slug>`.
+CREATE TABLE preference_changes (
+    preference_change_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    field TEXT NOT NULL,
+    old_value TEXT NULL,
+    new_value TEXT NULL,
+    changed_at timestamptz NOT NULL
+);
+CREATE INDEX preference_changes_subscriber_id_idx
+ON preference_changes (subscriber_id, changed_at);
+
+COMMIT;
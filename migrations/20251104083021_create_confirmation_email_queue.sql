@@ -0,0 +1,7 @@
+-- Confirmation emails waiting to be sent, for subscribers added in bulk.
+-- A new token replaces the old one, and takes its queued email with it.
+CREATE TABLE confirmation_email_queue (
+    subscription_token TEXT PRIMARY KEY
+    REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+    enqueued_at timestamptz NOT NULL
+);
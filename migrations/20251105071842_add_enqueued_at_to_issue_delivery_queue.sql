@@ -0,0 +1,3 @@
+-- Workers hand out the oldest task first.
+ALTER TABLE issue_delivery_queue ADD COLUMN enqueued_at timestamptz NOT NULL DEFAULT now();
+CREATE INDEX issue_delivery_queue_enqueued_at_idx ON issue_delivery_queue (enqueued_at);
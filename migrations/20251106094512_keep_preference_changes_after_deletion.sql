@@ -0,0 +1,3 @@
+-- The compliance trail outlives the subscriber: deleting them keeps the
+-- record of what they asked for, with the names they went by blanked out.
+ALTER TABLE preference_changes DROP CONSTRAINT preference_changes_subscriber_id_fkey;
@@ -0,0 +1,100 @@
+use super::{AuthError, Credentials, validate_credentials};
+use anyhow::{Context, anyhow};
+use axum::http::HeaderMap;
+use base64::Engine;
+use secrecy::SecretString;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Authenticates a request with the 'Basic' credentials in its headers.
+/// Missing or wrong credentials are turned into the caller's error through
+/// `unauthorized`, anything else through its `From<anyhow::Error>`.
+pub async fn authenticate_basic<E>(
+    headers: &HeaderMap,
+    db_pool: &PgPool,
+    unauthorized: impl FnOnce(anyhow::Error) -> E,
+) -> Result<Uuid, E>
+where
+    E: From<anyhow::Error>,
+{
+    let credentials = match basic_authentication(headers) {
+        Ok(credentials) => credentials,
+        Err(e) => return Err(unauthorized(e)),
+    };
+    validate_credentials(credentials, db_pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => unauthorized(e.into()),
+            AuthError::UnexpectedError(_) => E::from(e.into()),
+        })
+}
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or_else(|| anyhow!("A username and a password must be provided in 'Basic' auth."))?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: SecretString::from(password.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use secrecy::ExposeSecret;
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn valid_basic_credentials_are_decoded() {
+        // "user:pass:word"
+        let headers = headers_with("Basic dXNlcjpwYXNzOndvcmQ=");
+
+        let credentials = basic_authentication(&headers).expect("Failed to decode credentials");
+
+        assert_eq!(credentials.username, "user");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn missing_header_is_rejected() {
+        assert!(basic_authentication(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn non_basic_scheme_is_rejected() {
+        assert!(basic_authentication(&headers_with("Bearer dXNlcjpwYXNz")).is_err());
+    }
+
+    #[test]
+    fn invalid_base64_is_rejected() {
+        assert!(basic_authentication(&headers_with("Basic not-base64!")).is_err());
+    }
+
+    #[test]
+    fn credentials_without_a_colon_are_rejected() {
+        // "userpass"
+        assert!(basic_authentication(&headers_with("Basic dXNlcnBhc3M=")).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_messages::Messages;
+use tower_sessions::Session;
+use uuid::Uuid;
+
+pub const USER_ID_KEY: &str = "user_id";
+
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::ops::Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub async fn reject_anonymous_users(
+    session: Session,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match session.get::<Uuid>(USER_ID_KEY).await {
+        Ok(Some(user_id)) => {
+            request.extensions_mut().insert(UserId(user_id));
+            next.run(request).await
+        }
+        Ok(None) => {
+            // Not using the `Messages` extractor here: extracting it consumes
+            // the pending flash messages before the handler gets to show them.
+            if let Some(messages) = request.extensions().get::<Messages>() {
+                messages
+                    .clone()
+                    .error("You must be logged in to access that page.");
+            }
+            Redirect::to("/login").into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to read the session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
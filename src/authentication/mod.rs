@@ -0,0 +1,7 @@
+mod basic;
+mod middleware;
+mod password;
+
+pub use basic::{authenticate_basic, basic_authentication};
+pub use middleware::{USER_ID_KEY, UserId, reject_anonymous_users};
+pub use password::{AuthError, Credentials, change_password, validate_credentials};
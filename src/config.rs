@@ -1,4 +1,4 @@
-use crate::domain::SubscriberEmail;
+use crate::{domain::SubscriberEmail, email_client::RetryPolicy};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -9,12 +9,15 @@ pub struct Config {
     pub app: AppConfig,
     pub db: DbConfig,
     pub email: EmailConfig,
+    pub templates: TemplatesConfig,
+    pub tracking: TrackingConfig,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct AppConfig {
     pub port: u16,
     pub host: String,
+    pub hmac_secret: SecretString,
 }
 
 impl AppConfig {
@@ -49,6 +52,22 @@ impl DbConfig {
     }
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct TemplatesConfig {
+    pub directory: String,
+    /// Re-reads the templates whenever they change on disk. Meant for development.
+    #[serde(default)]
+    pub hot_reload: bool,
+}
+
+/// Global switches for open and click tracking. An issue is only tracked if
+/// both this and the issue itself allow it.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct TrackingConfig {
+    pub opens: bool,
+    pub clicks: bool,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct AppBaseUrl(pub String);
 
@@ -58,12 +77,74 @@ impl From<&str> for AppBaseUrl {
     }
 }
 
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct EmailConfig {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: AppBaseUrl,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    pub retry: RetryConfig,
+    pub smtp: Option<SmtpConfig>,
+    pub file: Option<FileSinkConfig>,
+    pub webhook: WebhookConfig,
+    /// Marks the subject of test sends, so they aren't mistaken for the real thing.
+    #[serde(default = "default_test_subject_prefix")]
+    pub test_subject_prefix: String,
+}
+
+fn default_test_subject_prefix() -> String {
+    "[TEST]".to_string()
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub jitter: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    None,
+    #[default]
+    #[serde(rename = "starttls")]
+    StartTls,
+    Tls,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FileSinkConfig {
+    pub directory: String,
+}
+
+/// Basic auth credentials the provider must present when posting events.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookConfig {
+    pub username: String,
+    pub password: SecretString,
 }
 
 impl EmailConfig {
@@ -74,6 +155,15 @@ impl EmailConfig {
     pub fn timeout(&self) -> Duration {
         Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.retry.max_attempts.max(1),
+            base_delay: Duration::from_millis(self.retry.base_delay_milliseconds),
+            max_delay: Duration::from_millis(self.retry.max_delay_milliseconds),
+            jitter: self.retry.jitter,
+        }
+    }
 }
 
 pub fn get_config() -> Result<Config, anyhow::Error> {
@@ -0,0 +1,171 @@
+use crate::{
+    config::AppBaseUrl, domain::NewSubscriber, email_client::EmailTransport,
+    issue_delivery_worker::ExecutionOutcome, preferences::PreferenceLinks,
+    routes::send_confirmation_email, templates::Templates,
+};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// Drains `confirmation_email_queue` forever. Like issue deliveries, tasks
+/// are claimed with `FOR UPDATE SKIP LOCKED`.
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    templates: Arc<Templates>,
+    preference_links: Arc<PreferenceLinks>,
+    base_url: AppBaseUrl,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &templates,
+            &preference_links,
+            &base_url.0,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to send a confirmation email");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    templates: &Templates,
+    preference_links: &PreferenceLinks,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut tx, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    if task.subscriber_status != "pending_confirmation" {
+        tracing::info!(
+            subscriber_id = %task.subscriber_id,
+            "Skipping a subscriber who is no longer pending confirmation",
+        );
+    } else {
+        match NewSubscriber::new(task.subscriber_email, task.subscriber_name) {
+            // A failed send is not retried: they can ask for a new link.
+            Ok(subscriber) => {
+                if let Err(e) = send_confirmation_email(
+                    email_client,
+                    templates,
+                    subscriber,
+                    &task.list_name,
+                    base_url,
+                    &task.subscription_token,
+                    &preference_links.link(task.subscriber_id),
+                )
+                .await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        subscriber_id = %task.subscriber_id,
+                        "Failed to send a confirmation email. Skipping.",
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    subscriber_id = %task.subscriber_id,
+                    "Skipping a pending subscriber. \
+                    Their stored contact details are invalid",
+                );
+            }
+        }
+    }
+
+    delete_task(&mut tx, &task.subscription_token).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Queues a confirmation email for a token that has just been stored.
+pub async fn enqueue_confirmation_email(
+    tx: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscription_token, enqueued_at)
+        VALUES ($1, $2)
+        "#,
+        subscription_token,
+        Utc::now(),
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+struct ConfirmationTask {
+    subscription_token: String,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    subscriber_name: String,
+    subscriber_status: String,
+    list_name: String,
+}
+
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, ConfirmationTask)>, anyhow::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let task = sqlx::query_as!(
+        ConfirmationTask,
+        r#"
+        SELECT
+            q.subscription_token,
+            t.subscriber_id,
+            s.email AS subscriber_email,
+            s.name AS subscriber_name,
+            s.status AS subscriber_status,
+            l.name AS list_name
+        FROM confirmation_email_queue AS q
+        INNER JOIN subscription_tokens AS t ON t.subscription_token = q.subscription_token
+        INNER JOIN subscriptions AS s ON s.id = t.subscriber_id
+        INNER JOIN lists AS l ON l.list_id = t.list_id
+        ORDER BY q.enqueued_at
+        LIMIT 1
+        FOR UPDATE OF q
+        SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .context("Failed to dequeue a confirmation email")?;
+
+    Ok(task.map(|task| (tx, task)))
+}
+
+async fn delete_task(
+    tx: &mut Transaction<'static, Postgres>,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscription_token = $1"#,
+        subscription_token,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to delete a confirmation email task")?;
+
+    Ok(())
+}
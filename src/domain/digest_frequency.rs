@@ -0,0 +1,54 @@
+use anyhow::anyhow;
+
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Immediately,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [
+        DigestFrequency::Immediately,
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| anyhow!("Unknown digest frequency: {s}"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediately => "immediately",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestFrequency;
+    use claims::assert_err;
+
+    #[test]
+    fn frequencies_round_trip_through_their_names() {
+        for frequency in DigestFrequency::ALL {
+            assert_eq!(
+                DigestFrequency::parse(frequency.as_str()).unwrap(),
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DigestFrequency::parse("hourly"));
+        assert_err!(DigestFrequency::parse("Weekly"));
+    }
+}
@@ -0,0 +1,62 @@
+use uuid::Uuid;
+
+/// The URL-friendly handle of a newsletter issue in the public archive: the
+/// title in lowercase ASCII, words joined by dashes, followed by the first
+/// characters of the issue id so that identical titles don't collide.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    pub fn new(title: &str, newsletter_issue_id: Uuid) -> Self {
+        let mut slug = String::new();
+        for c in title.chars().flat_map(char::to_lowercase) {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        let slug = match slug.trim_end_matches('-') {
+            "" => "issue",
+            slug => slug,
+        };
+        Self(format!(
+            "{}-{}",
+            slug,
+            &newsletter_issue_id.to_string()[..8]
+        ))
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> Uuid {
+        Uuid::parse_str("6c1e5f0e-3b1a-4d5e-9f4b-2a7c8d9e0f1a").unwrap()
+    }
+
+    #[test]
+    fn words_are_lowercased_and_joined_by_dashes() {
+        let slug = IssueSlug::new("Hello, World: Issue #3!", id());
+        assert_eq!(slug.as_ref(), "hello-world-issue-3-6c1e5f0e");
+    }
+
+    #[test]
+    fn non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::new("Ça va? Über gut", id());
+        assert_eq!(slug.as_ref(), "a-va-ber-gut-6c1e5f0e");
+    }
+
+    #[test]
+    fn titles_without_usable_characters_fall_back_to_issue() {
+        let slug = IssueSlug::new("🎉🎉🎉", id());
+        assert_eq!(slug.as_ref(), "issue-6c1e5f0e");
+    }
+}
@@ -1,4 +1,13 @@
+mod digest_frequency;
+mod issue_slug;
 mod subscriber;
+mod subscriber_tag;
+mod subscription_status;
 
+pub use digest_frequency::DigestFrequency;
+pub use issue_slug::IssueSlug;
 pub use subscriber::NewSubscriber;
 pub use subscriber::SubscriberEmail;
+pub use subscriber::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+pub use subscription_status::SubscriptionStatus;
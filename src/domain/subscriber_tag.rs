@@ -0,0 +1,58 @@
+use anyhow::anyhow;
+
+/// A label attached to subscribers to group them, e.g. `beta`. Tags are
+/// lowercased and made of ASCII letters, digits, `-` and `_`, so that they can
+/// be written as is in segment expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        let tag = s.trim().to_ascii_lowercase();
+        if tag.is_empty() {
+            return Err(anyhow!("Tag cannot be empty"));
+        }
+        if tag.len() > 64 {
+            return Err(anyhow!("Tag cannot be longer than 64 characters"));
+        }
+        if !tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!("Tag can only contain letters, digits, `-` and `_`"));
+        }
+        Ok(Self(tag))
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse("  Early-Adopter_2 ").unwrap();
+        assert_eq!(tag.as_ref(), "early-adopter_2");
+    }
+
+    #[test]
+    fn empty_or_long_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("   "));
+        assert_err!(SubscriberTag::parse(&"a".repeat(65)));
+        assert_ok!(SubscriberTag::parse(&"a".repeat(64)));
+    }
+
+    #[test]
+    fn tags_with_spaces_or_punctuation_are_rejected() {
+        for tag in ["two words", "beta!", "tag:beta", "é"] {
+            assert_err!(SubscriberTag::parse(tag), "{tag} was accepted");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use super::{EmailTransport, build_message};
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::Path;
+
+/// Writes every email as an `.eml` file into a directory instead of sending
+/// it. Meant for local development.
+pub struct FileTransport {
+    mailer: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl FileTransport {
+    pub fn new(
+        directory: impl AsRef<Path>,
+        sender: SubscriberEmail,
+    ) -> Result<Self, anyhow::Error> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        Ok(Self {
+            mailer: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FileTransport {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        let message_id = message.headers().get_raw("Message-ID").map(str::to_owned);
+        self.mailer
+            .send(message)
+            .await
+            .context("Failed to write the email to disk")?;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file() {
+        // Arrange
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let transport = FileTransport::new(&directory, sender).unwrap();
+
+        // Act
+        transport
+            .send_email(&recipient, "Subject", "<p>Hello</p>", "Hello")
+            .await
+            .unwrap();
+
+        // Assert
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let eml = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(eml.contains("To: recipient@example.com"));
+        assert!(eml.contains("Subject: Subject"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
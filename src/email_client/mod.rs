@@ -0,0 +1,107 @@
+mod file;
+mod postmark;
+mod smtp;
+
+pub use file::FileTransport;
+pub use postmark::{PostmarkTransport, RetryPolicy};
+pub use smtp::SmtpTransport;
+
+use crate::{
+    config::{EmailConfig, EmailTransportKind},
+    domain::SubscriberEmail,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    Message,
+    message::{
+        MultiPart,
+        header::{HeaderName, HeaderValue},
+    },
+};
+use std::sync::Arc;
+
+/// Delivers a single email. Implemented once per provider, so that the rest
+/// of the application doesn't need to know how emails leave the building.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// Sends an email carrying extra headers, given as `(name, value)` pairs.
+    /// Returns the id the provider assigned to the message, if it reports one.
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+}
+
+pub fn build_transport(config: &EmailConfig) -> Result<Arc<dyn EmailTransport>, anyhow::Error> {
+    let sender = config.sender()?;
+    let transport: Arc<dyn EmailTransport> = match config.transport {
+        EmailTransportKind::Postmark => Arc::new(PostmarkTransport::new(
+            config.base_url.0.clone(),
+            sender,
+            config.authorization_token.clone(),
+            config.timeout(),
+            config.retry_policy(),
+        )),
+        EmailTransportKind::Smtp => {
+            let smtp = config
+                .smtp
+                .as_ref()
+                .context("The `smtp` transport requires an `email.smtp` section")?;
+            Arc::new(SmtpTransport::new(smtp, sender, config.timeout())?)
+        }
+        EmailTransportKind::File => {
+            let file = config
+                .file
+                .as_ref()
+                .context("The `file` transport requires an `email.file` section")?;
+            Arc::new(FileTransport::new(&file.directory, sender)?)
+        }
+    };
+    Ok(transport)
+}
+
+/// Builds a MIME message with both an HTML and a plain-text alternative.
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[(&str, &str)],
+) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(sender.as_ref().parse().context("Invalid sender address")?)
+        .to(recipient
+            .as_ref()
+            .parse()
+            .context("Invalid recipient address")?)
+        .subject(subject)
+        .message_id(None);
+    for (name, value) in headers {
+        let name = HeaderName::new_from_ascii(name.to_string())
+            .with_context(|| format!("Invalid header name: {name}"))?;
+        builder = builder.raw_header(HeaderValue::new(name, value.to_string()));
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_owned(),
+            html_content.to_owned(),
+        ))
+        .context("Failed to build the email message")
+}
@@ -0,0 +1,136 @@
+//! Makes publisher HTML fit for an inbox.
+//!
+//! Mail clients drop `<style>` blocks and anything script-like, so rules are
+//! inlined into `style` attributes and the markup is cut down to an allowlist
+//! before an issue is stored.
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+/// Gmail clips messages whose HTML is larger than this.
+const MAX_HTML_BYTES: usize = 102 * 1024;
+
+static RE_IMG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<img\b[^>]*>").unwrap());
+static RE_ALT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\balt=""#).unwrap());
+static RE_SRC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\bsrc="([^"]*)""#).unwrap());
+
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_generic_attributes(["style"])
+        // Layout attributes that email clients still rely on.
+        .add_tag_attributes(
+            "table",
+            ["width", "border", "cellpadding", "cellspacing", "bgcolor"],
+        )
+        .add_tag_attributes("td", ["width", "valign", "bgcolor"])
+        .add_tag_attributes("th", ["width", "valign", "bgcolor"])
+        .clean_content_tags(HashSet::from(["script", "style", "title"]));
+    builder
+});
+
+/// HTML ready to be sent, along with what the publisher may want to fix.
+pub struct PreparedHtml {
+    pub html: String,
+    pub warnings: Vec<String>,
+}
+
+/// Inlines `<style>` rules, then strips everything outside the allowlist.
+/// External stylesheets are never fetched.
+pub fn prepare_html(html: &str) -> Result<PreparedHtml, anyhow::Error> {
+    let inlined = css_inline::CSSInliner::options()
+        .load_remote_stylesheets(false)
+        .build()
+        .inline(html)?;
+    let html = SANITIZER.clean(&inlined).to_string().trim().to_owned();
+    let warnings = warnings(&html);
+
+    Ok(PreparedHtml { html, warnings })
+}
+
+fn warnings(html: &str) -> Vec<String> {
+    let mut warnings: Vec<String> = RE_IMG
+        .find_iter(html)
+        .filter(|img| !RE_ALT.is_match(img.as_str()))
+        .map(|img| {
+            let src = RE_SRC
+                .captures(img.as_str())
+                .map_or("", |c| c.get(1).unwrap().as_str());
+            format!("The image {src} has no alt text.")
+        })
+        .collect();
+    if html.len() > MAX_HTML_BYTES {
+        warnings.push(format!(
+            "The HTML is {} KB; Gmail clips messages over {} KB.",
+            html.len() / 1024,
+            MAX_HTML_BYTES / 1024,
+        ));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prepare_html;
+
+    #[test]
+    fn style_rules_are_inlined_and_the_style_block_dropped() {
+        let html = r#"<style>p { color: red; } .lead { font-weight: bold; }</style>
+            <p class="lead">Hello</p>"#;
+
+        let prepared = prepare_html(html).unwrap();
+
+        assert_eq!(
+            prepared.html,
+            r#"<p style="color: red;font-weight: bold;">Hello</p>"#
+        );
+    }
+
+    #[test]
+    fn scripts_handlers_and_javascript_links_are_removed() {
+        let html = r#"<script>alert("hi")</script>
+            <p onclick="track()">Read <a href="javascript:alert(1)">this</a></p>"#;
+
+        let prepared = prepare_html(html).unwrap();
+
+        assert_eq!(
+            prepared.html,
+            r#"<p>Read <a rel="noopener noreferrer">this</a></p>"#
+        );
+    }
+
+    #[test]
+    fn merge_tags_survive_sanitization() {
+        let html = r#"<p>Hi {{subscriber.name}}, <a href="{{unsubscribe_url}}">leave</a></p>"#;
+
+        let prepared = prepare_html(html).unwrap();
+
+        assert!(prepared.html.contains("Hi {{subscriber.name}}"));
+        assert!(prepared.html.contains(r#"href="{{unsubscribe_url}}""#));
+    }
+
+    #[test]
+    fn images_without_alt_text_are_reported() {
+        let html = r#"<img src="https://example.com/cat.png">
+            <img src="https://example.com/dog.png" alt="A dog">
+            <img src="https://example.com/spacer.gif" alt="">"#;
+
+        let prepared = prepare_html(html).unwrap();
+
+        assert_eq!(
+            prepared.warnings,
+            vec!["The image https://example.com/cat.png has no alt text."]
+        );
+    }
+
+    #[test]
+    fn oversized_content_is_reported() {
+        let html = format!("<p>{}</p>", "a".repeat(110 * 1024));
+
+        let prepared = prepare_html(&html).unwrap();
+
+        assert_eq!(prepared.warnings.len(), 1);
+        assert!(prepared.warnings[0].contains("Gmail clips"));
+    }
+}
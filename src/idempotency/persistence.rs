@@ -0,0 +1,137 @@
+use super::IdempotencyKey;
+use anyhow::Context;
+use axum::{
+    body::{Body, to_bytes},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(Response),
+}
+
+/// Claims the idempotency key for this request, or hands back the response
+/// saved by the request that claimed it first.
+///
+/// A concurrent request with the same key blocks on the insert until the
+/// first transaction is committed or rolled back.
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let n_inserted_rows = sqlx::query!(
+        r#"
+    INSERT INTO idempotency (user_id, idempotency_key, created_at)
+    VALUES ($1, $2, $3)
+    ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        Utc::now(),
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to claim the idempotency key")?
+    .rows_affected();
+
+    if n_inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(tx))
+    } else {
+        let saved_response = get_saved_response(pool, idempotency_key, user_id)
+            .await?
+            .context("We expected a saved response, we didn't find it")?;
+        Ok(NextAction::ReturnSavedResponse(saved_response))
+    }
+}
+
+async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<Response>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+    SELECT
+        response_status_code as "response_status_code!",
+        response_headers as "response_headers!: Vec<HeaderPairRecord>",
+        response_body as "response_body!"
+    FROM idempotency
+    WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the saved response")?;
+
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+    let mut response = Response::builder().status(status_code);
+    for HeaderPairRecord { name, value } in r.response_headers {
+        response = response.header(name, value);
+    }
+    Ok(Some(response.body(Body::from(r.response_body))?))
+}
+
+pub async fn save_response(
+    mut tx: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    response: Response,
+) -> Result<Response, anyhow::Error> {
+    let (response_head, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .context("Failed to read the response body")?;
+    let status_code = response_head.status.as_u16() as i16;
+    let headers = response_head
+        .headers
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    sqlx::query_unchecked!(
+        r#"
+    UPDATE idempotency
+    SET
+        response_status_code = $3,
+        response_headers = $4,
+        response_body = $5
+    WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref(),
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to save the response")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    let response = (response_head, Body::from(body)).into_response();
+    Ok(response)
+}
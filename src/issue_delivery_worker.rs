@@ -0,0 +1,325 @@
+use crate::{
+    config::AppBaseUrl,
+    domain::SubscriberEmail,
+    email_client::EmailTransport,
+    preferences::PreferenceLinks,
+    rendering::{MergeValues, archive_link, render_issue, unsubscribe_link},
+    templates::Templates,
+    tracking::{IssueTracking, Tracker},
+};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Drains `issue_delivery_queue` forever, oldest task first. Tasks are
+/// claimed with `FOR UPDATE SKIP LOCKED`, so any number of workers can run
+/// side by side.
+///
+/// Subscribers on a daily or weekly digest have their issues held until the
+/// (UTC) day or week they were published in is over, then get them in one go.
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    templates: Arc<Templates>,
+    tracker: Arc<Tracker>,
+    preference_links: Arc<PreferenceLinks>,
+    base_url: AppBaseUrl,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &templates,
+            &tracker,
+            &preference_links,
+            &base_url.0,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to execute a delivery task");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    templates: &Templates,
+    tracker: &Tracker,
+    preference_links: &PreferenceLinks,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut tx, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    let outcome = if task.subscriber_status != "confirmed" || !task.on_issue_lists {
+        // They left between the issue being published and it being delivered.
+        tracing::info!(
+            subscriber_id = %task.subscriber_id,
+            "Skipping a subscriber who is no longer confirmed",
+        );
+        DeliveryOutcome::Failed("The subscriber is no longer confirmed".into())
+    } else {
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => {
+                let personalization = Personalization {
+                    templates,
+                    tracker,
+                    preference_links,
+                    base_url,
+                };
+                // A task that cannot be rendered would fail the same way on
+                // every attempt, so it is recorded as failed like any other:
+                // resuming the issue retries it.
+                deliver(pool, email_client, &personalization, &task, &email)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            newsletter_issue_id = %task.newsletter_issue_id,
+                            subscriber_id = %task.subscriber_id,
+                            "Failed to prepare an issue for a confirmed subscriber. Skipping.",
+                        );
+                        DeliveryOutcome::Failed(format!("{e:#}"))
+                    })
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    subscriber_id = %task.subscriber_id,
+                    "Skipping a confirmed subscriber. \
+                    Their stored contact details are invalid",
+                );
+                DeliveryOutcome::Failed(format!("{e:#}"))
+            }
+        }
+    };
+
+    record_delivery(&mut tx, &task, outcome).await?;
+    delete_task(&mut tx, &task).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+enum DeliveryOutcome {
+    Sent { provider_message_id: Option<String> },
+    Failed(String),
+}
+
+/// What it takes to turn an issue into the email one subscriber receives.
+struct Personalization<'a> {
+    templates: &'a Templates,
+    tracker: &'a Tracker,
+    preference_links: &'a PreferenceLinks,
+    base_url: &'a str,
+}
+
+async fn deliver(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    personalization: &Personalization<'_>,
+    task: &DeliveryTask,
+    email: &SubscriberEmail,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let Personalization {
+        templates,
+        tracker,
+        preference_links,
+        base_url,
+    } = personalization;
+    let issue = get_issue(pool, task.newsletter_issue_id).await?;
+    let html_content = tracker.instrument(
+        &issue.html_content,
+        IssueTracking {
+            opens: issue.track_opens,
+            clicks: issue.track_clicks,
+        },
+        task.newsletter_issue_id,
+        task.subscriber_id,
+    );
+    let rendered = render_issue(
+        templates,
+        &issue.title,
+        &html_content,
+        &issue.text_content,
+        &MergeValues {
+            subscriber_name: &task.subscriber_name,
+            unsubscribe_url: &unsubscribe_link(base_url, &task.unsubscribe_token),
+            preferences_url: &preference_links.link(task.subscriber_id),
+            archive_url: &archive_link(base_url, &issue.slug),
+        },
+    )?;
+    let outcome = match email_client
+        .send_email_with_headers(
+            email,
+            &rendered.subject,
+            &rendered.html,
+            &rendered.text,
+            &rendered.headers(),
+        )
+        .await
+    {
+        Ok(provider_message_id) => DeliveryOutcome::Sent {
+            provider_message_id,
+        },
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_id = %task.subscriber_id,
+                "Failed to deliver issue to a confirmed subscriber. Skipping.",
+            );
+            DeliveryOutcome::Failed(format!("{e:#}"))
+        }
+    };
+
+    Ok(outcome)
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    subscriber_name: String,
+    subscriber_status: String,
+    /// Whether they are still confirmed on one of the lists the issue went to.
+    on_issue_lists: bool,
+    unsubscribe_token: String,
+}
+
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, DeliveryTask)>, anyhow::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_id,
+            s.email AS subscriber_email,
+            s.name AS subscriber_name,
+            s.status AS subscriber_status,
+            EXISTS (
+                SELECT 1
+                FROM issue_lists AS il
+                INNER JOIN list_memberships AS m ON m.list_id = il.list_id
+                WHERE il.newsletter_issue_id = q.newsletter_issue_id
+                AND m.subscriber_id = q.subscriber_id
+                AND m.status = 'confirmed'
+            ) AS "on_issue_lists!",
+            s.unsubscribe_token
+        FROM issue_delivery_queue AS q
+        INNER JOIN subscriptions AS s ON q.subscriber_id = s.id
+        WHERE CASE s.digest_frequency
+            WHEN 'daily' THEN date_trunc('day', q.enqueued_at, 'UTC') + interval '1 day'
+            WHEN 'weekly' THEN date_trunc('week', q.enqueued_at, 'UTC') + interval '1 week'
+            ELSE q.enqueued_at
+        END <= now()
+        ORDER BY q.enqueued_at
+        LIMIT 1
+        FOR UPDATE OF q
+        SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .context("Failed to dequeue a delivery task")?;
+
+    Ok(task.map(|task| (tx, task)))
+}
+
+async fn record_delivery(
+    tx: &mut Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+    outcome: DeliveryOutcome,
+) -> Result<(), anyhow::Error> {
+    let (status, last_error, provider_message_id) = match outcome {
+        DeliveryOutcome::Sent {
+            provider_message_id,
+        } => ("sent", None, provider_message_id),
+        DeliveryOutcome::Failed(error) => ("failed", Some(error), None),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE deliveries
+        SET status = $3,
+            n_attempts = n_attempts + 1,
+            last_error = $4,
+            provider_message_id = COALESCE($5, provider_message_id),
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        status,
+        last_error,
+        provider_message_id,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to record the delivery outcome")?;
+
+    Ok(())
+}
+
+async fn delete_task(
+    tx: &mut Transaction<'static, Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to delete a delivery task")?;
+
+    Ok(())
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    slug: String,
+    track_opens: bool,
+    track_clicks: bool,
+}
+
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content, slug, track_opens, track_clicks
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fetch the newsletter issue")?;
+
+    Ok(issue)
+}
@@ -0,0 +1,49 @@
+use crate::routes::enqueue_delivery_tasks;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Publishes scheduled issues once their `send_at` has passed, handing them
+/// over to the delivery workers.
+pub async fn run_scheduler_until_stopped(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        if let Err(e) = publish_due_issues(&pool).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to publish scheduled issues");
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+/// Returns the number of issues that were published.
+pub async fn publish_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let due_issues = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now(), updated_at = now()
+        WHERE newsletter_issue_id IN (
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE status = 'scheduled' AND send_at <= now()
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING newsletter_issue_id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .context("Failed to publish due issues")?;
+
+    for issue in &due_issues {
+        tracing::info!(newsletter_issue_id = %issue.newsletter_issue_id, "Publishing a scheduled issue");
+        enqueue_delivery_tasks(&mut tx, issue.newsletter_issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(due_issues.len())
+}
@@ -1,5 +1,20 @@
+pub mod authentication;
 pub mod config;
+pub mod confirmation_email_worker;
 pub mod domain;
 pub mod email_client;
+pub mod email_html;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod plain_text;
+pub mod preferences;
+pub mod rendering;
 pub mod routes;
+pub mod segment;
+pub mod session_store;
+pub mod signing;
 pub mod startup;
+pub mod subscriber_import;
+pub mod templates;
+pub mod tracking;
@@ -1,42 +1,150 @@
-use newsletter::{config::get_config, email_client::EmailClient, startup::serve};
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use newsletter::{
+    config::get_config,
+    confirmation_email_worker,
+    email_client::build_transport,
+    issue_delivery_worker::run_worker_until_stopped,
+    issue_scheduler::run_scheduler_until_stopped,
+    preferences::PreferenceLinks,
+    routes::DEFAULT_LIST,
+    startup::{AppState, serve},
+    subscriber_import::{ImportMode, ImportOptions, import_subscribers},
+    templates::Templates,
+    tracking::Tracker,
+};
 use sqlx::PgPool;
+use std::{fs::File, path::PathBuf, sync::Arc};
 use tokio::net::TcpListener;
 use tracing::info;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter, fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serves the API and runs the scheduler and the delivery worker. This is
+    /// the default.
+    Serve,
+    /// Imports subscribers from a CSV file with `email` and `name` columns,
+    /// then prints a JSON report of what happened to each row.
+    ImportSubscribers {
+        /// The CSV file to import.
+        file: PathBuf,
+        /// `confirmed` or `pending_confirmation`.
+        #[arg(long, value_parser = ImportMode::parse)]
+        mode: ImportMode,
+        /// The slug of the list to add everyone to.
+        #[arg(long, default_value = DEFAULT_LIST)]
+        list: String,
+        /// Queue a confirmation email for each pending subscriber. The server
+        /// sends them.
+        #[arg(long)]
+        send_confirmations: bool,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    init_tracing();
-
-    info!("Starting server");
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
+    // The import report goes to stdout, so keep the logs out of its way.
+    init_tracing(match command {
+        Command::Serve => BoxMakeWriter::new(std::io::stdout),
+        Command::ImportSubscribers { .. } => BoxMakeWriter::new(std::io::stderr),
+    });
 
     let config = get_config()?;
     let db_pool = PgPool::connect_with(config.db.connect_options()).await?;
-    let sender_email = config.email.sender()?;
-    let timeout = config.email.timeout();
+    let email_client = build_transport(&config.email)?;
+    let templates = Arc::new(Templates::load(&config.templates)?);
     let base_url = config.email.base_url;
-    let email_client = EmailClient::new(
+    let tracker = Arc::new(Tracker::new(
+        config.tracking,
+        config.app.hmac_secret.clone(),
         base_url.0.clone(),
-        sender_email,
-        config.email.authorization_token,
-        timeout,
-    );
+    ));
+    let preference_links = Arc::new(PreferenceLinks::new(
+        config.app.hmac_secret.clone(),
+        base_url.0.clone(),
+    ));
+    let app_state = AppState {
+        db_pool: db_pool.clone(),
+        email_client: email_client.clone(),
+        base_url: base_url.clone(),
+        webhook_credentials: config.email.webhook,
+        test_subject_prefix: config.email.test_subject_prefix,
+        templates: templates.clone(),
+        tracker: tracker.clone(),
+        preference_links: preference_links.clone(),
+    };
+
+    if let Command::ImportSubscribers {
+        file,
+        mode,
+        list,
+        send_confirmations,
+    } = command
+    {
+        let input =
+            File::open(&file).with_context(|| format!("Failed to open {}", file.display()))?;
+        let options = ImportOptions {
+            mode,
+            list,
+            send_confirmations,
+        };
+        let report = import_subscribers(&db_pool, input, &options).await?;
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+        println!();
+        return Ok(());
+    }
+
+    info!("Starting server");
+
     let listener = TcpListener::bind(config.app.address()).await?;
 
     info!("listening on http://{} ", listener.local_addr()?);
 
-    Ok(serve(listener, db_pool, email_client, base_url)
-        .await?
-        .await?)
+    let server = serve(listener, app_state, config.app.hmac_secret).await?;
+    let scheduler = run_scheduler_until_stopped(db_pool.clone());
+    let confirmation_worker = confirmation_email_worker::run_worker_until_stopped(
+        db_pool.clone(),
+        email_client.clone(),
+        templates.clone(),
+        preference_links.clone(),
+        base_url.clone(),
+    );
+    let worker = run_worker_until_stopped(
+        db_pool,
+        email_client,
+        templates,
+        tracker,
+        preference_links,
+        base_url,
+    );
+
+    tokio::select! {
+        outcome = server.into_future() => outcome?,
+        outcome = worker => outcome?,
+        outcome = confirmation_worker => outcome?,
+        outcome = scheduler => outcome?,
+    }
+
+    Ok(())
 }
 
-fn init_tracing() {
+fn init_tracing(writer: BoxMakeWriter) {
     tracing_subscriber::registry()
         .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
             EnvFilter::builder()
                 .parse("newsletter=info,tower_http=trace,axum::rejection=trace")
                 .expect("failed to parse default tracing filter")
         }))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
         .init();
 }
@@ -0,0 +1,99 @@
+//! Plain text alternatives for HTML emails.
+
+/// The width plain text bodies are wrapped at.
+const LINE_WIDTH: usize = 78;
+
+/// Turns an HTML body into readable plain text. Links become numbered
+/// footnotes, headings and lists keep their shape, and scripts and styles are
+/// dropped.
+pub fn html_to_text(html: &str) -> Result<String, anyhow::Error> {
+    let text = html2text::config::plain_no_decorate()
+        .link_footnotes(true)
+        // A URL broken over two lines can't be clicked.
+        .no_link_wrapping()
+        .string_from_read(html.as_bytes(), LINE_WIDTH)?;
+    Ok(text.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_to_text;
+
+    #[test]
+    fn links_become_footnotes() {
+        let html = r#"<p>Read <a href="https://example.com/posts/1">the full post</a> or
+            <a href="https://example.com/archive">browse the archive</a>.</p>"#;
+
+        let text = html_to_text(html).unwrap();
+
+        assert_eq!(
+            text,
+            "Read [the full post][1] or [browse the archive][2].\n\n\
+             [1]: https://example.com/posts/1\n\
+             [2]: https://example.com/archive"
+        );
+    }
+
+    #[test]
+    fn headings_and_lists_keep_their_structure() {
+        let html = r#"
+            <h1>This week</h1>
+            <p>Three things worth your time:</p>
+            <ul>
+                <li>A new release</li>
+                <li>A conference talk</li>
+            </ul>
+            <h2>Next steps</h2>
+            <ol>
+                <li>Upgrade</li>
+                <li>Tell us how it went</li>
+            </ol>"#;
+
+        let text = html_to_text(html).unwrap();
+
+        assert_eq!(
+            text,
+            "# This week\n\n\
+             Three things worth your time:\n\
+             * A new release\n\
+             * A conference talk\n\n\
+             ## Next steps\n\
+             1. Upgrade\n\
+             2. Tell us how it went"
+        );
+    }
+
+    #[test]
+    fn scripts_and_styles_are_dropped() {
+        let html = r#"<!doctype html>
+            <html>
+            <head>
+                <style>p { color: #333; }</style>
+                <script>track("open");</script>
+            </head>
+            <body><p>Hello &amp; welcome!</p></body>
+            </html>"#;
+
+        let text = html_to_text(html).unwrap();
+
+        assert_eq!(text, "Hello & welcome!");
+    }
+
+    #[test]
+    fn long_lines_wrap_but_links_do_not() {
+        let link = format!("https://example.com/{}", "a".repeat(100));
+        let html = format!(
+            r#"<p>{} <a href="{link}">here</a></p>"#,
+            "All work and no play makes Jack a dull boy. ".repeat(3)
+        );
+
+        let text = html_to_text(&html).unwrap();
+
+        assert!(
+            text.lines()
+                .filter(|l| !l.starts_with('['))
+                .all(|l| l.len() <= 78)
+        );
+        assert!(text.ends_with(&format!("[1]: {link}")));
+    }
+}
@@ -0,0 +1,95 @@
+//! Links to a subscriber's preference center.
+//!
+//! Every email carries one. The token in the link names the subscriber and is
+//! signed with the app's HMAC secret; it doesn't expire, so the link in an old
+//! issue keeps working.
+
+use crate::signing;
+use secrecy::SecretString;
+use uuid::Uuid;
+
+/// Tells preference tokens apart from other signed tokens.
+const KIND: u8 = b'p';
+
+pub struct PreferenceLinks {
+    hmac_secret: SecretString,
+    base_url: String,
+}
+
+impl PreferenceLinks {
+    pub fn new(hmac_secret: SecretString, base_url: String) -> Self {
+        Self {
+            hmac_secret,
+            base_url,
+        }
+    }
+
+    pub fn link(&self, subscriber_id: Uuid) -> String {
+        let mut payload = vec![KIND];
+        payload.extend_from_slice(subscriber_id.as_bytes());
+        let token = signing::sign(&self.hmac_secret, payload);
+        format!("{}/preferences/{token}", self.base_url)
+    }
+
+    /// Stands in for a subscriber's link where there is no subscriber, as in
+    /// previews and test sends.
+    pub fn placeholder(&self) -> String {
+        format!("{}/preferences/preview", self.base_url)
+    }
+
+    /// Returns the subscriber the token was issued for, or `None` if it wasn't
+    /// issued by us.
+    pub fn verify(&self, token: &str) -> Option<Uuid> {
+        let payload = signing::verify(&self.hmac_secret, token)?;
+        match payload.split_first()? {
+            (&KIND, subscriber_id) => Uuid::from_slice(subscriber_id).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PreferenceLinks;
+    use crate::signing;
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    fn links() -> PreferenceLinks {
+        PreferenceLinks::new(
+            SecretString::from("a-secret"),
+            "https://news.example.com".to_string(),
+        )
+    }
+
+    fn token(link: &str) -> &str {
+        link.strip_prefix("https://news.example.com/preferences/")
+            .unwrap()
+    }
+
+    #[test]
+    fn links_verify_as_their_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let link = links().link(subscriber_id);
+
+        assert_eq!(links().verify(token(&link)), Some(subscriber_id));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let link = links().link(Uuid::new_v4());
+        let mut signed = token(&link).to_owned().into_bytes();
+        signed[3] = if signed[3] == b'A' { b'B' } else { b'A' };
+
+        assert_eq!(links().verify(std::str::from_utf8(&signed).unwrap()), None);
+    }
+
+    #[test]
+    fn tokens_signed_for_something_else_are_rejected() {
+        let secret = SecretString::from("a-secret");
+        let mut payload = vec![b'o'];
+        payload.extend_from_slice(Uuid::new_v4().as_bytes());
+
+        assert_eq!(links().verify(&signing::sign(&secret, payload)), None);
+    }
+}
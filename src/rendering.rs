@@ -0,0 +1,176 @@
+//! Turns a stored newsletter issue into the email a subscriber receives.
+
+use crate::templates::{NewsletterEmail, Templates};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// `{{tag}}`, with optional spaces around the tag name.
+static RE_MERGE_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([^{}]*?)\s*\}\}").unwrap());
+
+/// Stands in for the subscriber's name when there is no subscriber, as in
+/// previews and test sends.
+pub const PLACEHOLDER_NAME: &str = "Subscriber";
+
+/// An issue as sent to one subscriber.
+pub struct RenderedIssue {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    list_unsubscribe: String,
+}
+
+impl RenderedIssue {
+    /// The headers that let mail clients offer their own unsubscribe button,
+    /// including RFC 8058 one-click unsubscribe.
+    pub fn headers(&self) -> [(&str, &str); 2] {
+        [
+            ("List-Unsubscribe", &self.list_unsubscribe),
+            ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ]
+    }
+}
+
+/// What the merge tags in an issue stand for, for one recipient.
+pub struct MergeValues<'a> {
+    pub subscriber_name: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+    pub archive_url: &'a str,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, tag: &str) -> Option<&str> {
+        match tag {
+            "subscriber.name" => Some(self.subscriber_name),
+            "unsubscribe_url" => Some(self.unsubscribe_url),
+            "preferences_url" => Some(self.preferences_url),
+            "archive_url" => Some(self.archive_url),
+            _ => None,
+        }
+    }
+}
+
+pub fn unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!("{base_url}/subscriptions/unsubscribe?token={unsubscribe_token}")
+}
+
+pub fn archive_link(base_url: &str, slug: &str) -> String {
+    format!("{base_url}/archive/{slug}")
+}
+
+/// The merge tags in `content` that can't be filled in, each listed once.
+pub fn unknown_merge_tags(content: &str) -> Vec<String> {
+    let known = MergeValues {
+        subscriber_name: "",
+        unsubscribe_url: "",
+        preferences_url: "",
+        archive_url: "",
+    };
+    let mut unknown: Vec<String> = Vec::new();
+    for captures in RE_MERGE_TAG.captures_iter(content) {
+        let tag = &captures[0];
+        if known.get(&captures[1]).is_none() && !unknown.iter().any(|t| t == tag) {
+            unknown.push(tag.to_owned());
+        }
+    }
+    unknown
+}
+
+/// Replaces merge tags in HTML content, escaping the values.
+pub fn merge_html(content: &str, values: &MergeValues) -> String {
+    merge(content, values, htmlescape::encode_minimal)
+}
+
+/// Replaces merge tags in plain text content.
+pub fn merge_text(content: &str, values: &MergeValues) -> String {
+    merge(content, values, str::to_owned)
+}
+
+fn merge(content: &str, values: &MergeValues, encode: fn(&str) -> String) -> String {
+    RE_MERGE_TAG
+        .replace_all(content, |captures: &Captures| {
+            match values.get(&captures[1]) {
+                Some(value) => encode(value),
+                // Only issues stored before tags were checked can get here.
+                None => captures[0].to_owned(),
+            }
+        })
+        .into_owned()
+}
+
+/// Fills in the merge tags of an issue and wraps it in the newsletter layout,
+/// addressed to one subscriber.
+pub fn render_issue(
+    templates: &Templates,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    values: &MergeValues,
+) -> Result<RenderedIssue, anyhow::Error> {
+    let rendered = templates.render_newsletter(&NewsletterEmail {
+        name: values.subscriber_name,
+        title,
+        html_content: &merge_html(html_content, values),
+        text_content: &merge_text(text_content, values),
+        unsubscribe_url: values.unsubscribe_url,
+        preferences_url: values.preferences_url,
+    })?;
+    Ok(RenderedIssue {
+        subject: title.to_owned(),
+        html: rendered.html,
+        text: rendered.text,
+        list_unsubscribe: format!("<{}>", values.unsubscribe_url),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeValues, merge_html, merge_text, unknown_merge_tags};
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            subscriber_name: "Ursula & co",
+            unsubscribe_url: "https://example.com/subscriptions/unsubscribe?token=abc",
+            preferences_url: "https://example.com/preferences/abc",
+            archive_url: "https://example.com/archive/issue-1",
+        }
+    }
+
+    #[test]
+    fn known_tags_are_filled_in_with_or_without_spaces() {
+        let text = merge_text(
+            "Hi {{subscriber.name}}, read it online: {{ archive_url }}",
+            &values(),
+        );
+
+        assert_eq!(
+            text,
+            "Hi Ursula & co, read it online: https://example.com/archive/issue-1"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let html = merge_html("<p>Hi {{subscriber.name}}</p>", &values());
+
+        assert_eq!(html, "<p>Hi Ursula &amp; co</p>");
+    }
+
+    #[test]
+    fn unknown_tags_are_reported_once_each() {
+        let unknown = unknown_merge_tags(
+            "{{subscriber.name}} {{ subscriber.email }} {{coupon}} {{ subscriber.email }}",
+        );
+
+        assert_eq!(unknown, vec!["{{ subscriber.email }}", "{{coupon}}"]);
+    }
+
+    #[test]
+    fn content_without_tags_is_left_alone() {
+        let content = "Curly braces { like these } are fine.";
+
+        assert!(unknown_merge_tags(content).is_empty());
+        assert_eq!(merge_text(content, &values()), content);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{authentication::UserId, startup::AppState};
+use anyhow::Context;
+use axum::{
+    Extension,
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DashboardError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for DashboardError {
+    fn into_response(self) -> axum::response::Response {
+        tracing::error!("Failed to render the admin dashboard: {:?}", self);
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+pub async fn admin_dashboard(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Html<String>, DashboardError> {
+    let username = get_username(*user_id, &db_pool).await?;
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/password">Change password</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+        htmlescape::encode_minimal(&username)
+    )))
+}
+
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id,)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve a username.")?;
+
+    Ok(row.username)
+}
@@ -0,0 +1,15 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_messages::Messages;
+use tower_sessions::Session;
+
+pub async fn log_out(session: Session, messages: Messages) -> Response {
+    if let Err(e) = session.flush().await {
+        tracing::error!("Failed to flush the session: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    messages.info("You have successfully logged out.");
+    Redirect::to("/login").into_response()
+}
@@ -0,0 +1,9 @@
+mod dashboard;
+mod logout;
+mod password;
+mod subscribers;
+
+pub use dashboard::*;
+pub use logout::*;
+pub use password::*;
+pub use subscribers::*;
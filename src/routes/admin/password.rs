@@ -0,0 +1,131 @@
+use crate::{
+    authentication::{AuthError, Credentials, UserId, validate_credentials},
+    routes::get_username,
+    startup::AppState,
+};
+use axum::{
+    Extension, Form,
+    extract::State,
+    response::{Html, Redirect},
+};
+use axum_messages::Messages;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+use unicode_segmentation::UnicodeSegmentation;
+
+const MIN_PASSWORD_LENGTH: usize = 12;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+#[derive(Deserialize)]
+pub struct PasswordFormData {
+    current_password: SecretString,
+    new_password: SecretString,
+    new_password_check: SecretString,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangePasswordError {
+    #[error("You entered two different new passwords - the field values must match.")]
+    PasswordMismatch,
+    #[error(
+        "The new password must be between {MIN_PASSWORD_LENGTH} and {MAX_PASSWORD_LENGTH} characters long."
+    )]
+    InvalidLength,
+    #[error("The current password is incorrect.")]
+    InvalidCurrentPassword,
+    #[error("Something went wrong, your password has not been changed.")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub async fn change_password_form(messages: Messages) -> Html<String> {
+    let mut msg_html = String::new();
+    for message in messages {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(&message.message)
+        )
+        .unwrap();
+    }
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ))
+}
+
+pub async fn change_password(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    messages: Messages,
+    Form(form): Form<PasswordFormData>,
+) -> Redirect {
+    match try_change_password(user_id, form, &db_pool).await {
+        Ok(()) => {
+            messages.info("Your password has been changed.");
+        }
+        Err(e) => {
+            if let ChangePasswordError::UnexpectedError(_) = e {
+                tracing::error!("Failed to change password: {:?}", e);
+            }
+            messages.error(e.to_string());
+        }
+    }
+    Redirect::to("/admin/password")
+}
+
+async fn try_change_password(
+    user_id: UserId,
+    form: PasswordFormData,
+    db_pool: &PgPool,
+) -> Result<(), ChangePasswordError> {
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return Err(ChangePasswordError::PasswordMismatch);
+    }
+    let length = form.new_password.expose_secret().graphemes(true).count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return Err(ChangePasswordError::InvalidLength);
+    }
+
+    let username = get_username(*user_id, db_pool).await?;
+    let credentials = Credentials {
+        username,
+        password: form.current_password,
+    };
+    validate_credentials(credentials, db_pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => ChangePasswordError::InvalidCurrentPassword,
+            AuthError::UnexpectedError(e) => ChangePasswordError::UnexpectedError(e),
+        })?;
+
+    crate::authentication::change_password(*user_id, form.new_password, db_pool).await?;
+
+    Ok(())
+}
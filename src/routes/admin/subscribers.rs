@@ -0,0 +1,564 @@
+use crate::authentication::authenticate_basic;
+use crate::domain::{SubscriberName, SubscriptionStatus};
+use crate::routes::{DEFAULT_LIST, delete_subscriber_tokens, record_preference_change};
+use crate::startup::AppState;
+use crate::subscriber_import::{
+    ImportError, ImportMode, ImportOptions, ImportReport, import_subscribers,
+};
+use anyhow::Context;
+use axum::{
+    Json,
+    body::Body,
+    extract::{
+        Path, Query, State,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use serde_json::Value;
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use uuid::Uuid;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdminApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber with id {0}.")]
+    NotFound(Uuid),
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// The body of every error response: a stable, machine-readable `error` and
+/// a human-readable `message`.
+#[derive(serde::Serialize)]
+pub struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl IntoResponse for AdminApiError {
+    fn into_response(self) -> Response {
+        let (status_code, error) = match &self {
+            AdminApiError::ValidationError(_) => (StatusCode::BAD_REQUEST, "validation_error"),
+            AdminApiError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            AdminApiError::AuthError(_) => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            AdminApiError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+            }
+        };
+        let message = match &self {
+            AdminApiError::UnexpectedError(e) => {
+                tracing::error!("Failed to manage subscribers: {:?}", e);
+                "Something went wrong.".to_string()
+            }
+            _ => self.to_string(),
+        };
+        let mut response = (status_code, Json(ErrorBody { error, message })).into_response();
+        if let AdminApiError::AuthError(_) = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="admin""#),
+            );
+        }
+        response
+    }
+}
+
+impl From<QueryRejection> for AdminApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        AdminApiError::ValidationError(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AdminApiError {
+    fn from(rejection: PathRejection) -> Self {
+        AdminApiError::ValidationError(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for AdminApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        AdminApiError::ValidationError(rejection.body_text())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberFilters {
+    status: Option<String>,
+    /// Matches subscribers whose email or name starts with it, ignoring case.
+    search: Option<String>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    subscribers: Vec<SubscriberSummary>,
+    /// Pass it back as `cursor` to get the next page. `null` on the last one.
+    next_cursor: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDetails {
+    #[serde(flatten)]
+    summary: SubscriberSummary,
+    digest_frequency: String,
+    /// The slugs of the lists they are confirmed on.
+    lists: Vec<String>,
+    tags: Vec<String>,
+    attributes: Value,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberUpdate {
+    name: Option<String>,
+    status: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportParameters {
+    /// `confirmed` or `pending_confirmation`.
+    mode: String,
+    list: Option<String>,
+    #[serde(default)]
+    send_confirmations: bool,
+}
+
+/// Lists subscribers in email order, one page at a time. Pages are keyed on
+/// the last email seen rather than an offset, so they stay stable while
+/// people keep signing up.
+pub async fn list_subscribers(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    filters: Result<Query<SubscriberFilters>, QueryRejection>,
+) -> Result<Json<SubscriberPage>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Query(filters) = filters?;
+
+    let status = filters
+        .status
+        .map(|s| SubscriptionStatus::parse(&s))
+        .transpose()
+        .map_err(|e| AdminApiError::ValidationError(e.to_string()))?;
+    // A prefix of an email or a name has to be a valid name in its own
+    // right: no markup, not blank and not absurdly long.
+    let search = filters
+        .search
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|e| AdminApiError::ValidationError(format!("Invalid search: {e}")))?;
+    let cursor = filters.cursor.map(|c| decode_cursor(&c)).transpose()?;
+    let limit = filters.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(AdminApiError::ValidationError(format!(
+            "`limit` must be between 1 and {MAX_LIMIT}"
+        )));
+    }
+
+    let mut subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+    SELECT id, email, name, status, subscribed_at
+    FROM subscriptions
+    WHERE ($1::text IS NULL OR status = $1)
+        AND ($2::text IS NULL OR email ILIKE $2 || '%' OR name ILIKE $2 || '%')
+        AND ($3::text IS NULL OR email > $3)
+    ORDER BY email
+    LIMIT $4
+        "#,
+        status.map(|s| s.as_str()),
+        search.as_ref().map(|s| escape_like(s.as_ref())),
+        cursor,
+        limit + 1,
+    )
+    .fetch_all(&db_pool)
+    .await
+    .context("Failed to fetch subscribers")?;
+
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|s| encode_cursor(&s.email))
+    } else {
+        None
+    };
+
+    Ok(Json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+/// Cursors are opaque to clients: the last email of a page, base64url-encoded.
+fn encode_cursor(email: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(email)
+}
+
+fn decode_cursor(cursor: &str) -> Result<String, AdminApiError> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| AdminApiError::ValidationError("Invalid cursor".into()))
+}
+
+pub async fn get_subscriber(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<SubscriberDetails>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+
+    Ok(Json(fetch_subscriber(&db_pool, subscriber_id).await?))
+}
+
+/// Renames a subscriber or changes their status. Changes are recorded in
+/// `preference_changes`, like the ones subscribers make themselves.
+pub async fn update_subscriber(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+    body: Result<Json<SubscriberUpdate>, JsonRejection>,
+) -> Result<Json<SubscriberDetails>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+    let Json(body) = body?;
+
+    let name = body
+        .name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|e| AdminApiError::ValidationError(e.to_string()))?;
+    let status = body
+        .status
+        .map(|s| SubscriptionStatus::parse(&s))
+        .transpose()
+        .map_err(|e| AdminApiError::ValidationError(e.to_string()))?;
+    if name.is_none() && status.is_none() {
+        return Err(AdminApiError::ValidationError(
+            "Nothing to update: expected `name` or `status`".into(),
+        ));
+    }
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let current = sqlx::query!(
+        r#"SELECT name, status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .context("Failed to look up the subscriber")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+
+    if let Some(name) = name.filter(|name| name.as_ref() != current.name) {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+            subscriber_id,
+            name.as_ref(),
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to rename the subscriber")?;
+        record_preference_change(
+            &mut tx,
+            subscriber_id,
+            "name",
+            Some(&current.name),
+            Some(name.as_ref()),
+        )
+        .await?;
+    }
+    if let Some(status) = status.filter(|status| status.as_str() != current.status) {
+        set_status(&mut tx, subscriber_id, &current.status, status).await?;
+    }
+    tx.commit()
+        .await
+        .context("Failed to commit the subscriber update")?;
+
+    Ok(Json(fetch_subscriber(&db_pool, subscriber_id).await?))
+}
+
+/// Deletes a subscriber and everything we hold about them.
+pub async fn delete_subscriber(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<StatusCode, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .context("Failed to look up the subscriber")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+    delete_subscriber_data(&mut tx, subscriber_id, &subscriber.email)
+        .await
+        .context("Failed to delete the subscriber's data")?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete the subscriber")?;
+    tx.commit()
+        .await
+        .context("Failed to commit the subscriber deletion")?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Imports subscribers from the CSV in the request body. The body is read as
+/// it arrives rather than buffered, so large exports can be sent as they are.
+pub async fn import_subscribers_from_csv(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Result<Query<ImportParameters>, QueryRejection>,
+    body: Body,
+) -> Result<Json<ImportReport>, AdminApiError> {
+    authenticate_basic(&headers, &app_state.db_pool, AdminApiError::AuthError).await?;
+    let Query(parameters) = parameters?;
+    let options = ImportOptions {
+        mode: ImportMode::parse(&parameters.mode)
+            .map_err(|e| AdminApiError::ValidationError(e.to_string()))?,
+        list: parameters.list.unwrap_or_else(|| DEFAULT_LIST.to_string()),
+        send_confirmations: parameters.send_confirmations,
+    };
+
+    let input = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let report = import_subscribers(&app_state.db_pool, SyncIoBridge::new(input), &options)
+        .await
+        .map_err(|e| match e {
+            ImportError::InvalidInput(message) => AdminApiError::ValidationError(message),
+            ImportError::UnexpectedError(e) => AdminApiError::UnexpectedError(e),
+        })?;
+
+    Ok(Json(report))
+}
+
+async fn fetch_subscriber(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<SubscriberDetails, AdminApiError> {
+    let row = sqlx::query!(
+        r#"
+    SELECT
+        s.id,
+        s.email,
+        s.name,
+        s.status,
+        s.subscribed_at,
+        s.digest_frequency,
+        s.attributes,
+        ARRAY(
+            SELECT l.slug
+            FROM list_memberships AS m
+            INNER JOIN lists AS l ON l.list_id = m.list_id
+            WHERE m.subscriber_id = s.id AND m.status = 'confirmed'
+            ORDER BY l.slug
+        ) AS "lists!",
+        ARRAY(
+            SELECT t.tag
+            FROM subscriber_tags AS t
+            WHERE t.subscriber_id = s.id
+            ORDER BY t.tag
+        ) AS "tags!"
+    FROM subscriptions AS s
+    WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch the subscriber")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+
+    Ok(SubscriberDetails {
+        summary: SubscriberSummary {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: row.status,
+            subscribed_at: row.subscribed_at,
+        },
+        digest_frequency: row.digest_frequency,
+        lists: row.lists,
+        tags: row.tags,
+        attributes: row.attributes,
+    })
+}
+
+/// Updates the subscriber's status and brings their list memberships along:
+/// confirming them confirms their pending memberships, and any status that
+/// stops delivery unsubscribes them from every list.
+async fn set_status(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    old_status: &str,
+    status: SubscriptionStatus,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        status.as_str(),
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to update the subscriber's status")?;
+
+    match status {
+        SubscriptionStatus::PendingConfirmation => {}
+        SubscriptionStatus::Confirmed => {
+            sqlx::query!(
+                r#"
+        UPDATE list_memberships
+        SET status = 'confirmed', updated_at = now()
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+                "#,
+                subscriber_id,
+            )
+            .execute(&mut **tx)
+            .await
+            .context("Failed to confirm the subscriber's memberships")?;
+        }
+        SubscriptionStatus::Unsubscribed
+        | SubscriptionStatus::Bounced
+        | SubscriptionStatus::Complained => {
+            sqlx::query!(
+                r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed', updated_at = now()
+        WHERE subscriber_id = $1
+                "#,
+                subscriber_id,
+            )
+            .execute(&mut **tx)
+            .await
+            .context("Failed to unsubscribe the subscriber from their lists")?;
+            delete_subscriber_tokens(tx, subscriber_id)
+                .await
+                .context("Failed to invalidate the subscriber's confirmation links")?;
+        }
+    }
+
+    record_preference_change(
+        tx,
+        subscriber_id,
+        "status",
+        Some(old_status),
+        Some(status.as_str()),
+    )
+    .await
+}
+
+/// Removes the rows that reference the subscriber without cascading, and the
+/// provider's events about them, which carry their address in the payload.
+/// Their preference changes are kept for compliance, minus the names.
+async fn delete_subscriber_data(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    DELETE FROM email_events
+    WHERE lower(email) = lower($2)
+        OR provider_message_id IN (
+            SELECT provider_message_id FROM deliveries WHERE subscriber_id = $1
+        )
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM tracking_events WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM deliveries WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM list_memberships WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"
+    UPDATE preference_changes SET old_value = NULL, new_value = NULL
+    WHERE subscriber_id = $1 AND field = 'name'
+        "#,
+        subscriber_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Escapes the characters `LIKE` treats specially, so a search for
+/// `ursula_` does not also match `ursulax`.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_like;
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("ursula_le%guin"), r"ursula\_le\%guin");
+        assert_eq!(escape_like(r"a\b"), r"a\\b");
+    }
+}
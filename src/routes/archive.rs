@@ -0,0 +1,97 @@
+use crate::rendering::{MergeValues, PLACEHOLDER_NAME, archive_link, merge_html};
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ArchiveError {
+    #[error("There is no published issue at this address.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ArchiveError {
+    fn into_response(self) -> axum::response::Response {
+        let status_code = match self {
+            ArchiveError::NotFound => StatusCode::NOT_FOUND,
+            ArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if let ArchiveError::UnexpectedError(e) = &self {
+            tracing::error!(error.cause_chain = ?e, "Failed to render an archived issue");
+        }
+        (status_code, self.to_string()).into_response()
+    }
+}
+
+/// The public web version of a published issue.
+pub async fn archived_issue(
+    State(AppState {
+        db_pool, base_url, ..
+    }): State<AppState>,
+    Path(slug): Path<String>,
+) -> Result<Html<String>, ArchiveError> {
+    let issue = get_published_issue(&db_pool, &slug)
+        .await
+        .context("Failed to fetch the archived issue")?
+        .ok_or(ArchiveError::NotFound)?;
+
+    let title = htmlescape::encode_minimal(&issue.title);
+    // Nobody in particular reads the public copy, so there is nothing to
+    // unsubscribe from either.
+    let html_content = merge_html(
+        &issue.html_content,
+        &MergeValues {
+            subscriber_name: PLACEHOLDER_NAME,
+            unsubscribe_url: "#",
+            preferences_url: "#",
+            archive_url: &archive_link(&base_url.0, &slug),
+        },
+    );
+    Ok(Html(format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p><time datetime="{published_at}">{published_on}</time></p>
+    {html_content}
+</body>
+</html>"#,
+        published_at = issue.published_at.to_rfc3339(),
+        published_on = issue.published_at.format("%B %-d, %Y"),
+        html_content = html_content,
+    )))
+}
+
+struct ArchivedIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+async fn get_published_issue(
+    db_pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+    SELECT title, html_content, published_at AS "published_at!"
+    FROM newsletter_issues
+    WHERE slug = $1 AND status = 'published'
+        "#,
+        slug,
+    )
+    .fetch_optional(db_pool)
+    .await
+}
@@ -0,0 +1,111 @@
+use crate::{
+    authentication::{AuthError, Credentials, USER_ID_KEY, validate_credentials},
+    startup::AppState,
+};
+use anyhow::Context;
+use axum::{
+    Form,
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_messages::Messages;
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::fmt::Write;
+use tower_sessions::Session;
+
+#[derive(Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: SecretString,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub async fn login_form(messages: Messages) -> Html<String> {
+    let mut error_html = String::new();
+    for message in messages {
+        writeln!(
+            error_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(&message.message)
+        )
+        .unwrap();
+    }
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {error_html}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+    ))
+}
+
+pub async fn login(
+    State(AppState { db_pool, .. }): State<AppState>,
+    session: Session,
+    messages: Messages,
+    Form(form): Form<LoginFormData>,
+) -> Response {
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+
+    match try_login(credentials, &db_pool, &session).await {
+        Ok(()) => Redirect::to("/admin/dashboard").into_response(),
+        Err(e) => {
+            if let LoginError::UnexpectedError(_) = e {
+                tracing::error!("Failed to log in: {:?}", e);
+            }
+            messages.error(e.to_string());
+            Redirect::to("/login").into_response()
+        }
+    }
+}
+
+async fn try_login(
+    credentials: Credentials,
+    db_pool: &sqlx::PgPool,
+    session: &Session,
+) -> Result<(), LoginError> {
+    let user_id = validate_credentials(credentials, db_pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+        })?;
+
+    // A new session id on login prevents session fixation.
+    session
+        .cycle_id()
+        .await
+        .context("Failed to renew the session id")?;
+    session
+        .insert(USER_ID_KEY, user_id)
+        .await
+        .context("Failed to store the user id in the session")?;
+
+    Ok(())
+}
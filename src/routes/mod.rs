@@ -1,9 +1,25 @@
+mod admin;
+mod archive;
 mod health;
+mod login;
 mod newsletters;
+mod preferences;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 
+pub use admin::*;
+pub use archive::*;
 pub use health::*;
+pub use login::*;
 pub use newsletters::*;
+pub use preferences::*;
+pub use subscribers::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
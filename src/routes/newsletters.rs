@@ -1,96 +0,0 @@
-use crate::domain::SubscriberEmail;
-use crate::startup::AppState;
-use anyhow::Context;
-use axum::Json;
-use axum::response::{IntoResponse, Response};
-use axum::{extract::State, http::StatusCode};
-use sqlx::PgPool;
-
-#[derive(serde::Deserialize)]
-pub struct BodyData {
-    title: String,
-    content: Content,
-}
-
-#[derive(serde::Deserialize)]
-pub struct Content {
-    html: String,
-    text: String,
-}
-
-#[derive(thiserror::Error, Debug)]
-pub enum PublishError {
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl IntoResponse for PublishError {
-    fn into_response(self) -> Response {
-        let body = format!("{:?}", self);
-        let status = match self {
-            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-        (status, body).into_response()
-    }
-}
-
-pub async fn publish_newsletter(
-    State(AppState {
-        db_pool,
-        email_client,
-        ..
-    }): State<AppState>,
-    Json(body): Json<BodyData>,
-) -> Result<impl IntoResponse, PublishError> {
-    let subscribers = get_confirmed_subscribers(&db_pool).await?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",
-                );
-            }
-        }
-    }
-    Ok(StatusCode::OK)
-}
-
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
-
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
-    Ok(confirmed_subscribers)
-}
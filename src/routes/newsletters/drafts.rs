@@ -0,0 +1,278 @@
+use super::issues::{IssueDetails, fetch_issue};
+use super::publish::{
+    PreparedContent, authenticate, get_issue_status, store_issue_links, store_issue_lists,
+};
+use super::{BodyData, PublishError};
+use crate::domain::IssueSlug;
+use crate::rendering::{
+    MergeValues, PLACEHOLDER_NAME, archive_link, render_issue, unsubscribe_link,
+};
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Html,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleData {
+    send_at: DateTime<Utc>,
+}
+
+/// The saved draft, along with what the publisher may want to fix before
+/// sending it.
+#[derive(serde::Serialize)]
+pub struct DraftResponse {
+    #[serde(flatten)]
+    issue: IssueDetails,
+    warnings: Vec<String>,
+}
+
+pub async fn create_draft(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<BodyData>,
+) -> Result<(StatusCode, Json<DraftResponse>), PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    tracing::info!(%user_id, "Creating a draft issue");
+    body.validate()?;
+    let content = body.content.prepare()?;
+
+    let mut tx = begin(&db_pool).await?;
+    let newsletter_issue_id = insert_draft(&mut tx, &body, &content, user_id)
+        .await
+        .context("Failed to store the draft")?;
+    store_issue_lists(&mut tx, newsletter_issue_id, &body.lists).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(DraftResponse {
+            issue: fetch_issue(&db_pool, newsletter_issue_id).await?,
+            warnings: content.warnings,
+        }),
+    ))
+}
+
+pub async fn update_draft(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<BodyData>,
+) -> Result<Json<DraftResponse>, PublishError> {
+    authenticate(&headers, &db_pool).await?;
+    body.validate()?;
+    let content = body.content.prepare()?;
+
+    let slug = IssueSlug::new(&body.title, newsletter_issue_id);
+    let mut tx = begin(&db_pool).await?;
+    lock_unpublished_issue(&mut tx, newsletter_issue_id).await?;
+    sqlx::query!(
+        r#"
+    UPDATE newsletter_issues
+    SET title = $2, text_content = $3, html_content = $4, slug = $5,
+        track_opens = $6, track_clicks = $7, segment = $8, updated_at = now()
+    WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        body.title,
+        content.text,
+        content.html,
+        slug.as_ref(),
+        body.tracking.opens,
+        body.tracking.clicks,
+        body.segment,
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to update the draft")?;
+    store_issue_links(&mut tx, newsletter_issue_id, &content.html)
+        .await
+        .context("Failed to store the links of the draft")?;
+    store_issue_lists(&mut tx, newsletter_issue_id, &body.lists).await?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(Json(DraftResponse {
+        issue: fetch_issue(&db_pool, newsletter_issue_id).await?,
+        warnings: content.warnings,
+    }))
+}
+
+pub async fn delete_draft(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<StatusCode, PublishError> {
+    authenticate(&headers, &db_pool).await?;
+
+    let mut tx = begin(&db_pool).await?;
+    lock_unpublished_issue(&mut tx, newsletter_issue_id).await?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to delete the draft")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Renders the issue the way subscribers will see it.
+pub async fn preview_newsletter(
+    State(AppState {
+        db_pool,
+        base_url,
+        templates,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Html<String>, PublishError> {
+    authenticate(&headers, &db_pool).await?;
+
+    let issue = fetch_issue(&db_pool, newsletter_issue_id).await?;
+    let rendered = render_issue(
+        &templates,
+        &issue.summary.title,
+        &issue.content.html,
+        &issue.content.text,
+        &MergeValues {
+            subscriber_name: PLACEHOLDER_NAME,
+            unsubscribe_url: &unsubscribe_link(&base_url.0, "preview"),
+            preferences_url: &preference_links.placeholder(),
+            archive_url: &archive_link(&base_url.0, &issue.summary.slug),
+        },
+    )?;
+
+    Ok(Html(rendered.html))
+}
+
+/// Schedules a draft, or reschedules a scheduled issue. The scheduler picks it
+/// up once `send_at` has passed, so a time in the past sends it right away.
+pub async fn schedule_newsletter(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<ScheduleData>,
+) -> Result<Json<IssueDetails>, PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    tracing::info!(%user_id, %newsletter_issue_id, send_at = %body.send_at, "Scheduling an issue");
+
+    let mut tx = begin(&db_pool).await?;
+    lock_unpublished_issue(&mut tx, newsletter_issue_id).await?;
+    sqlx::query!(
+        r#"
+    UPDATE newsletter_issues
+    SET status = 'scheduled', send_at = $2, updated_at = now()
+    WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        body.send_at,
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to schedule the issue")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(Json(fetch_issue(&db_pool, newsletter_issue_id).await?))
+}
+
+/// Turns a scheduled issue back into a draft.
+pub async fn cancel_newsletter(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<IssueDetails>, PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    tracing::info!(%user_id, %newsletter_issue_id, "Cancelling a scheduled issue");
+
+    let mut tx = begin(&db_pool).await?;
+    if lock_unpublished_issue(&mut tx, newsletter_issue_id).await? != "scheduled" {
+        return Err(PublishError::Conflict(
+            "Only scheduled issues can be cancelled.".into(),
+        ));
+    }
+    sqlx::query!(
+        r#"
+    UPDATE newsletter_issues
+    SET status = 'draft', send_at = NULL, updated_at = now()
+    WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Failed to cancel the scheduled issue")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(Json(fetch_issue(&db_pool, newsletter_issue_id).await?))
+}
+
+async fn begin(db_pool: &PgPool) -> Result<Transaction<'static, Postgres>, PublishError> {
+    let tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    Ok(tx)
+}
+
+/// Locks an issue that can still be changed and returns its status. Once an
+/// issue has been published it is out of the door and stays as it was sent.
+async fn lock_unpublished_issue(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<String, PublishError> {
+    let status = get_issue_status(tx, newsletter_issue_id)
+        .await
+        .context("Failed to look up the newsletter issue")?
+        .ok_or(PublishError::NotFound(newsletter_issue_id))?;
+    if status == "published" {
+        return Err(PublishError::Conflict(
+            "The issue has already been published and can no longer be changed.".into(),
+        ));
+    }
+    Ok(status)
+}
+
+async fn insert_draft(
+    tx: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+    content: &PreparedContent,
+    author_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let slug = IssueSlug::new(&body.title, newsletter_issue_id);
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_issues (
+        newsletter_issue_id, title, text_content, html_content, author_id, slug,
+        track_opens, track_clicks, segment, status, created_at, updated_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'draft', $10, $10)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        content.text,
+        content.html,
+        author_id,
+        slug.as_ref(),
+        body.tracking.opens,
+        body.tracking.clicks,
+        body.segment,
+        now,
+    )
+    .execute(&mut **tx)
+    .await?;
+    store_issue_links(tx, newsletter_issue_id, &content.html).await?;
+
+    Ok(newsletter_issue_id)
+}
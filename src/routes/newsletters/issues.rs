@@ -0,0 +1,217 @@
+use super::PublishError;
+use super::publish::{Tracking, authenticate};
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+/// Keeps `(page - 1) * per_page` from overflowing.
+const MAX_PAGE: i64 = i64::MAX / MAX_PER_PAGE;
+
+#[derive(serde::Deserialize)]
+pub struct Pagination {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssuePage {
+    issues: Vec<IssueSummary>,
+    page: i64,
+    per_page: i64,
+    total: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueSummary {
+    id: Uuid,
+    pub(super) title: String,
+    pub(super) slug: String,
+    author: Option<String>,
+    status: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    send_at: Option<DateTime<Utc>>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueDetails {
+    #[serde(flatten)]
+    pub(super) summary: IssueSummary,
+    pub(super) content: IssueContent,
+    tracking: Tracking,
+    /// The slugs of the lists the issue goes out to.
+    lists: Vec<String>,
+    segment: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueContent {
+    pub(super) html: String,
+    pub(super) text: String,
+}
+
+/// Lists issues, most recent first.
+pub async fn list_newsletters(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Query(pagination): Query<Pagination>,
+    headers: HeaderMap,
+) -> Result<Json<IssuePage>, PublishError> {
+    authenticate(&headers, &db_pool).await?;
+
+    let page = pagination.page.unwrap_or(1);
+    let per_page = pagination.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PAGE).contains(&page) {
+        return Err(PublishError::ValidationError(format!(
+            "`page` must be between 1 and {MAX_PAGE}"
+        )));
+    }
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(PublishError::ValidationError(format!(
+            "`per_page` must be between 1 and {MAX_PER_PAGE}"
+        )));
+    }
+
+    let issues = get_issues(&db_pool, per_page, (page - 1) * per_page)
+        .await
+        .context("Failed to fetch newsletter issues")?;
+    let total = count_issues(&db_pool)
+        .await
+        .context("Failed to count newsletter issues")?;
+
+    Ok(Json(IssuePage {
+        issues,
+        page,
+        per_page,
+        total,
+    }))
+}
+
+pub async fn get_newsletter(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<IssueDetails>, PublishError> {
+    authenticate(&headers, &db_pool).await?;
+
+    Ok(Json(fetch_issue(&db_pool, newsletter_issue_id).await?))
+}
+
+pub(super) async fn fetch_issue(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<IssueDetails, PublishError> {
+    get_issue(db_pool, newsletter_issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue")?
+        .ok_or(PublishError::NotFound(newsletter_issue_id))
+}
+
+async fn get_issues(
+    db_pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<IssueSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+    SELECT
+        i.newsletter_issue_id AS id,
+        i.title,
+        i.slug,
+        u.username AS "author?",
+        i.status,
+        i.created_at,
+        i.updated_at,
+        i.send_at,
+        i.published_at
+    FROM newsletter_issues AS i
+    LEFT JOIN users AS u ON u.user_id = i.author_id
+    ORDER BY i.created_at DESC, i.newsletter_issue_id
+    LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset,
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+async fn count_issues(db_pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(db_pool)
+        .await?;
+
+    Ok(row.count)
+}
+
+async fn get_issue(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueDetails>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT
+        i.newsletter_issue_id AS id,
+        i.title,
+        i.slug,
+        u.username AS "author?",
+        i.status,
+        i.created_at,
+        i.updated_at,
+        i.send_at,
+        i.published_at,
+        i.html_content,
+        i.text_content,
+        i.track_opens,
+        i.track_clicks,
+        i.segment,
+        ARRAY(
+            SELECT l.slug
+            FROM issue_lists AS il
+            INNER JOIN lists AS l ON l.list_id = il.list_id
+            WHERE il.newsletter_issue_id = i.newsletter_issue_id
+            ORDER BY l.slug
+        ) AS "lists!"
+    FROM newsletter_issues AS i
+    LEFT JOIN users AS u ON u.user_id = i.author_id
+    WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(row.map(|r| IssueDetails {
+        summary: IssueSummary {
+            id: r.id,
+            title: r.title,
+            slug: r.slug,
+            author: r.author,
+            status: r.status,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+            send_at: r.send_at,
+            published_at: r.published_at,
+        },
+        content: IssueContent {
+            html: r.html_content,
+            text: r.text_content,
+        },
+        tracking: Tracking {
+            opens: r.track_opens,
+            clicks: r.track_clicks,
+        },
+        lists: r.lists,
+        segment: r.segment,
+    }))
+}
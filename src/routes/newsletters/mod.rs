@@ -0,0 +1,9 @@
+mod drafts;
+mod issues;
+mod publish;
+mod send_test;
+
+pub use drafts::*;
+pub use issues::*;
+pub use publish::*;
+pub use send_test::*;
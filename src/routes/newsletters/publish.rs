@@ -0,0 +1,451 @@
+use crate::authentication::authenticate_basic;
+use crate::domain::IssueSlug;
+use crate::email_html::{PreparedHtml, prepare_html};
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::plain_text::html_to_text;
+use crate::rendering::unknown_merge_tags;
+use crate::routes::DEFAULT_LIST;
+use crate::segment::Segment;
+use crate::startup::AppState;
+use crate::tracking::trackable_links;
+use anyhow::Context;
+use axum::Json;
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    pub(super) title: String,
+    pub(super) content: Content,
+    #[serde(default)]
+    pub(super) tracking: Tracking,
+    /// The slugs of the lists the issue goes out to.
+    #[serde(default = "default_lists")]
+    pub(super) lists: Vec<String>,
+    /// Narrows the issue down to the subscribers matching this expression.
+    #[serde(default)]
+    pub(super) segment: Option<String>,
+}
+
+fn default_lists() -> Vec<String> {
+    vec![DEFAULT_LIST.to_string()]
+}
+
+/// Opt-ins for open and click tracking, both off unless asked for.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct Tracking {
+    #[serde(default)]
+    pub(super) opens: bool,
+    #[serde(default)]
+    pub(super) clicks: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    pub(super) html: String,
+    /// Generated from the HTML when left out or blank.
+    pub(super) text: Option<String>,
+}
+
+/// The bodies of an issue as they are stored and sent.
+pub(super) struct PreparedContent {
+    pub(super) html: String,
+    pub(super) text: String,
+    pub(super) warnings: Vec<String>,
+}
+
+impl Content {
+    /// Cleans up the HTML and settles on a plain text body, either as given or
+    /// generated from the cleaned HTML.
+    pub(super) fn prepare(&self) -> Result<PreparedContent, anyhow::Error> {
+        let PreparedHtml { html, warnings } =
+            prepare_html(&self.html).context("Failed to clean up the HTML body")?;
+        let text = match &self.text {
+            Some(text) if !text.trim().is_empty() => text.clone(),
+            _ => html_to_text(&html).context("Failed to generate the plain text body")?,
+        };
+        Ok(PreparedContent {
+            html,
+            text,
+            warnings,
+        })
+    }
+}
+
+impl BodyData {
+    /// Rejects merge tags that can't be filled in, before anything is sent.
+    pub(super) fn validate(&self) -> Result<(), PublishError> {
+        let mut unknown = unknown_merge_tags(&self.content.html);
+        let text = self.content.text.as_deref().unwrap_or_default();
+        for tag in unknown_merge_tags(text) {
+            if !unknown.contains(&tag) {
+                unknown.push(tag);
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(PublishError::ValidationError(format!(
+                "Unknown merge tags: {}",
+                unknown.join(", ")
+            )));
+        }
+        if self.lists.is_empty() {
+            return Err(PublishError::ValidationError(
+                "An issue must go out to at least one list".into(),
+            ));
+        }
+        if let Some(segment) = &self.segment {
+            Segment::parse(segment)
+                .map_err(|e| PublishError::ValidationError(format!("Invalid segment: {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Things the publisher may want to fix in future issues.
+#[derive(serde::Serialize)]
+pub struct PublishResponse {
+    warnings: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no newsletter issue with id {0}.")]
+    NotFound(Uuid),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for PublishError {
+    fn into_response(self) -> Response {
+        let body = format!("{:?}", self);
+        match self {
+            PublishError::AuthError(_) => (
+                StatusCode::UNAUTHORIZED,
+                [(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="publish""#),
+                )],
+                body,
+            )
+                .into_response(),
+            PublishError::ValidationError(_) => (StatusCode::BAD_REQUEST, body).into_response(),
+            PublishError::NotFound(_) => (StatusCode::NOT_FOUND, body).into_response(),
+            PublishError::Conflict(_) => (StatusCode::CONFLICT, body).into_response(),
+            PublishError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        }
+    }
+}
+
+pub async fn publish_newsletter(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<BodyData>,
+) -> Result<Response, PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    tracing::info!(%user_id, "Publishing a newsletter issue");
+    body.validate()?;
+    let content = body.content.prepare()?;
+
+    let idempotency_key = get_idempotency_key(&headers)?;
+    let mut tx = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(&db_pool, idempotency_key, user_id).await? {
+            NextAction::StartProcessing(tx) => tx,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => db_pool
+            .begin()
+            .await
+            .context("Failed to begin a transaction")?,
+    };
+
+    let issue_id = insert_newsletter_issue(&mut tx, &body, &content, user_id)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    store_issue_lists(&mut tx, issue_id, &body.lists).await?;
+    enqueue_delivery_tasks(&mut tx, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    let response = (
+        StatusCode::ACCEPTED,
+        Json(PublishResponse {
+            warnings: content.warnings,
+        }),
+    )
+        .into_response();
+    let response = match idempotency_key {
+        Some(idempotency_key) => save_response(tx, &idempotency_key, user_id, response).await?,
+        None => {
+            tx.commit().await.context("Failed to commit transaction")?;
+            response
+        }
+    };
+
+    Ok(response)
+}
+
+/// Queues the issue again for every confirmed subscriber who hasn't received
+/// it yet, e.g. after deliveries failed halfway through a publish.
+pub async fn resume_newsletter(
+    State(AppState { db_pool, .. }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    tracing::info!(%user_id, %newsletter_issue_id, "Resuming a newsletter issue");
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let status = get_issue_status(&mut tx, newsletter_issue_id)
+        .await
+        .context("Failed to look up the newsletter issue")?
+        .ok_or(PublishError::NotFound(newsletter_issue_id))?;
+    if status != "published" {
+        return Err(PublishError::Conflict(
+            "Only published issues can be resumed.".into(),
+        ));
+    }
+    enqueue_delivery_tasks(&mut tx, newsletter_issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// Records the links click tracking may redirect to, replacing any previous ones.
+pub(super) async fn store_issue_links(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    html: &str,
+) -> Result<(), sqlx::Error> {
+    let urls = trackable_links(html);
+    let link_indexes: Vec<i32> = (0..urls.len() as i32).collect();
+    sqlx::query!(
+        r#"DELETE FROM issue_links WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"
+    INSERT INTO issue_links (newsletter_issue_id, link_index, url)
+    SELECT $1, * FROM UNNEST($2::int4[], $3::text[])
+        "#,
+        newsletter_issue_id,
+        &link_indexes,
+        &urls,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Sets the lists an issue goes out to, rejecting slugs that don't name one.
+pub(super) async fn store_issue_lists(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    slugs: &[String],
+) -> Result<(), PublishError> {
+    let lists = sqlx::query!(
+        r#"SELECT list_id, slug FROM lists WHERE slug = ANY($1)"#,
+        slugs,
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .context("Failed to look up the lists")?;
+    let unknown: Vec<&str> = slugs
+        .iter()
+        .filter(|slug| !lists.iter().any(|list| &list.slug == *slug))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(PublishError::ValidationError(format!(
+            "Unknown lists: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    let list_ids: Vec<Uuid> = lists.iter().map(|list| list.list_id).collect();
+    sqlx::query!(
+        r#"DELETE FROM issue_lists WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to clear the lists of the issue")?;
+    sqlx::query!(
+        r#"
+    INSERT INTO issue_lists (newsletter_issue_id, list_id)
+    SELECT $1, * FROM UNNEST($2::uuid[])
+        "#,
+        newsletter_issue_id,
+        &list_ids,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to store the lists of the issue")?;
+
+    Ok(())
+}
+
+pub(super) async fn authenticate(
+    headers: &HeaderMap,
+    db_pool: &PgPool,
+) -> Result<Uuid, PublishError> {
+    authenticate_basic(headers, db_pool, PublishError::AuthError).await
+}
+
+fn get_idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, PublishError> {
+    let Some(value) = headers.get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .map_err(|_| {
+            PublishError::ValidationError("The idempotency key must be valid ASCII".into())
+        })?
+        .to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| PublishError::ValidationError(e.to_string()))?;
+
+    Ok(Some(key))
+}
+
+async fn insert_newsletter_issue(
+    tx: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+    content: &PreparedContent,
+    author_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let slug = IssueSlug::new(&body.title, newsletter_issue_id);
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+    INSERT INTO newsletter_issues (
+        newsletter_issue_id, title, text_content, html_content, author_id, slug,
+        track_opens, track_clicks, segment, status, created_at, updated_at, published_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'published', $10, $10, $10)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        content.text,
+        content.html,
+        author_id,
+        slug.as_ref(),
+        body.tracking.opens,
+        body.tracking.clicks,
+        body.segment,
+        now,
+    )
+    .execute(&mut **tx)
+    .await?;
+    store_issue_links(tx, newsletter_issue_id, &content.html).await?;
+
+    Ok(newsletter_issue_id)
+}
+
+/// Looks up the status of an issue, locking it until the transaction ends.
+pub(super) async fn get_issue_status(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT status
+    FROM newsletter_issues
+    WHERE newsletter_issue_id = $1
+    FOR UPDATE
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(row.map(|r| r.status))
+}
+
+/// Queues the issue for every confirmed subscriber on any of its lists, and in
+/// its segment if it has one, that hasn't been sent it yet, and records a
+/// `queued` delivery for each of them. Bounced deliveries are not retried: the
+/// address is known to be unreachable.
+pub async fn enqueue_delivery_tasks(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"SELECT segment FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    let segment = issue
+        .segment
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .context("The issue has an invalid segment")?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+    WITH enqueued AS (
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id)
+        SELECT DISTINCT il.newsletter_issue_id, s.id
+        FROM subscriptions AS s
+        INNER JOIN list_memberships AS m ON m.subscriber_id = s.id
+        INNER JOIN issue_lists AS il ON il.list_id = m.list_id
+        WHERE il.newsletter_issue_id = "#,
+    );
+    query.push_bind(newsletter_issue_id);
+    query.push(
+        r#"
+        AND s.status = 'confirmed'
+        AND m.status = 'confirmed'
+        AND NOT EXISTS (
+            SELECT 1 FROM deliveries AS d
+            WHERE d.newsletter_issue_id = il.newsletter_issue_id
+            AND d.subscriber_id = s.id
+            AND d.status IN ('sent', 'bounced')
+        )"#,
+    );
+    if let Some(segment) = segment {
+        query.push("\n        AND (");
+        segment.push_sql(&mut query);
+        query.push(")");
+    }
+    query.push(
+        r#"
+        ON CONFLICT DO NOTHING
+        RETURNING newsletter_issue_id, subscriber_id
+    )
+    INSERT INTO deliveries (newsletter_issue_id, subscriber_id, status)
+    SELECT newsletter_issue_id, subscriber_id, 'queued'
+    FROM enqueued
+    ON CONFLICT (newsletter_issue_id, subscriber_id)
+    DO UPDATE SET status = 'queued', updated_at = now()
+        "#,
+    );
+    query.build().execute(&mut **tx).await?;
+
+    Ok(())
+}
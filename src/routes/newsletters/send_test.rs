@@ -0,0 +1,111 @@
+use super::PublishError;
+use super::issues::fetch_issue;
+use super::publish::authenticate;
+use crate::domain::SubscriberEmail;
+use crate::rendering::{
+    MergeValues, PLACEHOLDER_NAME, archive_link, render_issue, unsubscribe_link,
+};
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use uuid::Uuid;
+
+/// A test send goes out synchronously; keep it to a handful of seed addresses.
+const MAX_TEST_RECIPIENTS: usize = 20;
+
+#[derive(serde::Deserialize)]
+pub struct TestSendData {
+    recipients: Vec<String>,
+}
+
+/// Mails an issue, in any state, to the given addresses rather than to
+/// subscribers. Nothing is recorded: test sends don't count as deliveries.
+pub async fn send_test_newsletter(
+    State(AppState {
+        db_pool,
+        email_client,
+        base_url,
+        test_subject_prefix,
+        templates,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Path(newsletter_issue_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<TestSendData>,
+) -> Result<StatusCode, PublishError> {
+    let user_id = authenticate(&headers, &db_pool).await?;
+    let recipients = parse_recipients(body.recipients)?;
+    tracing::info!(
+        %user_id,
+        %newsletter_issue_id,
+        n_recipients = recipients.len(),
+        "Sending a test issue",
+    );
+
+    let issue = fetch_issue(&db_pool, newsletter_issue_id).await?;
+    // There is no subscriber behind a test send, hence no real token.
+    let rendered = render_issue(
+        &templates,
+        &issue.summary.title,
+        &issue.content.html,
+        &issue.content.text,
+        &MergeValues {
+            subscriber_name: PLACEHOLDER_NAME,
+            unsubscribe_url: &unsubscribe_link(&base_url.0, "test"),
+            preferences_url: &preference_links.placeholder(),
+            archive_url: &archive_link(&base_url.0, &issue.summary.slug),
+        },
+    )?;
+    let subject = format!("{} {}", test_subject_prefix, rendered.subject);
+    let subject = subject.trim_start();
+
+    for recipient in &recipients {
+        email_client
+            .send_email_with_headers(
+                recipient,
+                subject,
+                &rendered.html,
+                &rendered.text,
+                &rendered.headers(),
+            )
+            .await
+            .with_context(|| format!("Failed to send a test issue to {recipient}"))?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+fn parse_recipients(recipients: Vec<String>) -> Result<Vec<SubscriberEmail>, PublishError> {
+    if recipients.is_empty() {
+        return Err(PublishError::ValidationError(
+            "At least one recipient is required.".into(),
+        ));
+    }
+    if recipients.len() > MAX_TEST_RECIPIENTS {
+        return Err(PublishError::ValidationError(format!(
+            "A test issue can be sent to at most {MAX_TEST_RECIPIENTS} recipients."
+        )));
+    }
+
+    let mut valid = Vec::with_capacity(recipients.len());
+    let mut invalid = Vec::new();
+    for recipient in recipients {
+        match SubscriberEmail::parse(recipient.clone()) {
+            Ok(email) => valid.push(email),
+            Err(_) => invalid.push(recipient),
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(PublishError::ValidationError(format!(
+            "Invalid recipient addresses: {}",
+            invalid.join(", ")
+        )));
+    }
+
+    Ok(valid)
+}
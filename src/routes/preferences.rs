@@ -0,0 +1,441 @@
+use crate::domain::{DigestFrequency, SubscriberName};
+use crate::routes::delete_subscriber_tokens;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    Form,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PreferencesError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("This link is not valid.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for PreferencesError {
+    fn into_response(self) -> axum::response::Response {
+        let status_code = match self {
+            PreferencesError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PreferencesError::UnknownToken => StatusCode::NOT_FOUND,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        tracing::error!("Failed to manage preferences: {}", self);
+        (status_code, self.to_string()).into_response()
+    }
+}
+
+/// What the preferences form sends: `name`, `digest_frequency`, and a `list`
+/// field for each list that is ticked.
+struct PreferencesForm {
+    name: SubscriberName,
+    digest_frequency: DigestFrequency,
+    lists: Vec<String>,
+}
+
+impl TryFrom<Vec<(String, String)>> for PreferencesForm {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| format!("Missing field: {name}"))
+        };
+        Ok(Self {
+            name: SubscriberName::parse(field("name")?).map_err(|e| e.to_string())?,
+            digest_frequency: DigestFrequency::parse(&field("digest_frequency")?)
+                .map_err(|e| e.to_string())?,
+            lists: fields
+                .iter()
+                .filter(|(key, _)| key == "list")
+                .map(|(_, value)| value.clone())
+                .collect(),
+        })
+    }
+}
+
+pub async fn preferences_form(
+    State(AppState {
+        db_pool,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Html<String>, PreferencesError> {
+    let subscriber_id = preference_links
+        .verify(&token)
+        .ok_or(PreferencesError::UnknownToken)?;
+
+    render_page(&db_pool, subscriber_id, &token, None).await
+}
+
+/// Applies the preferences form. Every change is recorded in
+/// `preference_changes`.
+pub async fn update_preferences(
+    State(AppState {
+        db_pool,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Path(token): Path<String>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Html<String>, PreferencesError> {
+    let subscriber_id = preference_links
+        .verify(&token)
+        .ok_or(PreferencesError::UnknownToken)?;
+    let form: PreferencesForm = fields
+        .try_into()
+        .map_err(PreferencesError::ValidationError)?;
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let subscriber = get_subscriber(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber")?
+        .ok_or(PreferencesError::UnknownToken)?;
+    let memberships = get_memberships(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's lists")?;
+    if let Some(unknown) = form
+        .lists
+        .iter()
+        .find(|slug| !memberships.iter().any(|m| &m.slug == *slug))
+    {
+        return Err(PreferencesError::ValidationError(format!(
+            "Unknown list: {unknown}"
+        )));
+    }
+
+    if subscriber.name != form.name.as_ref() {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+            subscriber_id,
+            form.name.as_ref(),
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update the subscriber's name")?;
+        record_preference_change(
+            &mut tx,
+            subscriber_id,
+            "name",
+            Some(&subscriber.name),
+            Some(form.name.as_ref()),
+        )
+        .await?;
+    }
+    if subscriber.digest_frequency != form.digest_frequency.as_str() {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET digest_frequency = $2 WHERE id = $1"#,
+            subscriber_id,
+            form.digest_frequency.as_str(),
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update the digest frequency")?;
+        record_preference_change(
+            &mut tx,
+            subscriber_id,
+            "digest_frequency",
+            Some(&subscriber.digest_frequency),
+            Some(form.digest_frequency.as_str()),
+        )
+        .await?;
+    }
+    for membership in &memberships {
+        let status = if form.lists.contains(&membership.slug) {
+            "confirmed"
+        } else if membership.status.is_some() {
+            "unsubscribed"
+        } else {
+            continue;
+        };
+        if membership.status.as_deref() != Some(status) {
+            set_membership(&mut tx, subscriber_id, membership, status).await?;
+        }
+    }
+    // Following a link sent to the address proves it is theirs, so picking a
+    // list is as good as confirming it. Bounces and complaints stand.
+    let resubscribed = !form.lists.is_empty()
+        && ["pending_confirmation", "unsubscribed"].contains(&subscriber.status.as_str());
+    if resubscribed {
+        set_status(&mut tx, subscriber_id, &subscriber.status, "confirmed").await?;
+    }
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    render_page(
+        &db_pool,
+        subscriber_id,
+        &token,
+        Some("Your preferences have been saved."),
+    )
+    .await
+}
+
+/// Takes the subscriber off every list.
+pub async fn unsubscribe_from_preferences(
+    State(AppState {
+        db_pool,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Html<&'static str>, PreferencesError> {
+    let subscriber_id = preference_links
+        .verify(&token)
+        .ok_or(PreferencesError::UnknownToken)?;
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let subscriber = get_subscriber(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber")?
+        .ok_or(PreferencesError::UnknownToken)?;
+    let memberships = get_memberships(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's lists")?;
+    for membership in &memberships {
+        if membership.status.is_some() && membership.status.as_deref() != Some("unsubscribed") {
+            set_membership(&mut tx, subscriber_id, membership, "unsubscribed").await?;
+        }
+    }
+    if subscriber.status != "unsubscribed" {
+        set_status(&mut tx, subscriber_id, &subscriber.status, "unsubscribed").await?;
+    }
+    delete_subscriber_tokens(&mut tx, subscriber_id)
+        .await
+        .context("Failed to invalidate the subscriber's confirmation links")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(Html(
+        "<p>You have been unsubscribed. You will not receive any further issues.</p>",
+    ))
+}
+
+/// Adds an entry to the audit trail of a subscriber's preferences.
+pub async fn record_preference_change(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO preference_changes
+        (preference_change_id, subscriber_id, field, old_value, new_value, changed_at)
+    VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        field,
+        old_value,
+        new_value,
+        Utc::now(),
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to record a preference change")?;
+
+    Ok(())
+}
+
+async fn render_page(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    token: &str,
+    notice: Option<&str>,
+) -> Result<Html<String>, PreferencesError> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let subscriber = get_subscriber(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber")?
+        .ok_or(PreferencesError::UnknownToken)?;
+    let memberships = get_memberships(&mut tx, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's lists")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    let mut lists_html = String::new();
+    for membership in &memberships {
+        lists_html.push_str(&format!(
+            r#"
+            <label><input type="checkbox" name="list" value="{}"{}> {}</label><br>"#,
+            htmlescape::encode_minimal(&membership.slug),
+            if membership.status.as_deref() == Some("confirmed") {
+                " checked"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&membership.name),
+        ));
+    }
+    let mut frequencies_html = String::new();
+    for frequency in DigestFrequency::ALL {
+        frequencies_html.push_str(&format!(
+            r#"
+            <option value="{0}"{1}>{0}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == subscriber.digest_frequency {
+                " selected"
+            } else {
+                ""
+            },
+        ));
+    }
+    let notice_html = notice
+        .map(|notice| format!("<p><i>{notice}</i></p>"))
+        .unwrap_or_default();
+    let token = htmlescape::encode_minimal(token);
+
+    Ok(Html(format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>Your preferences</title>
+</head>
+<body>
+    <h1>Your preferences</h1>
+    {notice_html}
+    <p>Subscribed as {email}.</p>
+    <form action="/preferences/{token}" method="post">
+        <label>Name
+            <input type="text" name="name" value="{name}">
+        </label>
+        <fieldset>
+            <legend>Lists</legend>{lists_html}
+        </fieldset>
+        <label>How often
+            <select name="digest_frequency">{frequencies_html}
+            </select>
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/preferences/{token}/unsubscribe" method="post">
+        <button type="submit">Unsubscribe from everything</button>
+    </form>
+</body>
+</html>"#,
+        email = htmlescape::encode_minimal(&subscriber.email),
+        name = htmlescape::encode_minimal(&subscriber.name),
+    )))
+}
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    digest_frequency: String,
+}
+
+async fn get_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+    SELECT email, name, status, digest_frequency
+    FROM subscriptions
+    WHERE id = $1
+    FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+/// Every list, with the subscriber's membership of it if they have one.
+struct Membership {
+    list_id: Uuid,
+    slug: String,
+    name: String,
+    status: Option<String>,
+}
+
+async fn get_memberships(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<Membership>, sqlx::Error> {
+    sqlx::query_as!(
+        Membership,
+        r#"
+    SELECT l.list_id, l.slug, l.name, m.status AS "status?"
+    FROM lists AS l
+    LEFT JOIN list_memberships AS m
+        ON m.list_id = l.list_id AND m.subscriber_id = $1
+    ORDER BY l.name
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut **tx)
+    .await
+}
+
+async fn set_membership(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    membership: &Membership,
+    status: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_id, subscriber_id, status)
+    VALUES ($1, $2, $3)
+    ON CONFLICT (list_id, subscriber_id)
+    DO UPDATE SET status = $3, updated_at = now()
+        "#,
+        membership.list_id,
+        subscriber_id,
+        status,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to update a list membership")?;
+    record_preference_change(
+        tx,
+        subscriber_id,
+        &format!("list:{}", membership.slug),
+        membership.status.as_deref(),
+        Some(status),
+    )
+    .await
+}
+
+async fn set_status(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    old_status: &str,
+    status: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        status,
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Failed to update the subscriber's status")?;
+    record_preference_change(tx, subscriber_id, "status", Some(old_status), Some(status)).await
+}
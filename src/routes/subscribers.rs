@@ -0,0 +1,158 @@
+use crate::authentication::authenticate_basic;
+use crate::domain::SubscriberTag;
+use crate::routes::AdminApiError;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::{
+        Path, State,
+        rejection::{JsonRejection, PathRejection},
+    },
+    http::{HeaderMap, StatusCode},
+};
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The tags of a subscriber, in alphabetical order.
+pub async fn get_subscriber_tags(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<Vec<String>>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+    ensure_subscriber_exists(&db_pool, subscriber_id).await?;
+
+    let tags = sqlx::query!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscriber_id,
+    )
+    .fetch_all(&db_pool)
+    .await
+    .context("Failed to fetch the subscriber's tags")?;
+
+    Ok(Json(tags.into_iter().map(|r| r.tag).collect()))
+}
+
+/// Tags a subscriber. Tagging them twice with the same tag is a no-op.
+pub async fn add_subscriber_tag(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+) -> Result<StatusCode, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path((subscriber_id, tag)) = path?;
+    let tag =
+        SubscriberTag::parse(&tag).map_err(|e| AdminApiError::ValidationError(e.to_string()))?;
+    ensure_subscriber_exists(&db_pool, subscriber_id).await?;
+
+    sqlx::query!(
+        r#"
+    INSERT INTO subscriber_tags (subscriber_id, tag)
+    VALUES ($1, $2)
+    ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag.as_ref(),
+    )
+    .execute(&db_pool)
+    .await
+    .context("Failed to tag the subscriber")?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove_subscriber_tag(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+) -> Result<StatusCode, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path((subscriber_id, tag)) = path?;
+    let tag =
+        SubscriberTag::parse(&tag).map_err(|e| AdminApiError::ValidationError(e.to_string()))?;
+    ensure_subscriber_exists(&db_pool, subscriber_id).await?;
+
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2"#,
+        subscriber_id,
+        tag.as_ref(),
+    )
+    .execute(&db_pool)
+    .await
+    .context("Failed to untag the subscriber")?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_subscriber_attributes(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<Value>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+
+    let row = sqlx::query!(
+        r#"SELECT attributes FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_optional(&db_pool)
+    .await
+    .context("Failed to fetch the subscriber's attributes")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+
+    Ok(Json(row.attributes))
+}
+
+/// Merges the given attributes into the subscriber's, JSON Merge Patch style:
+/// keys set to `null` are removed, others are added or replaced.
+pub async fn update_subscriber_attributes(
+    State(AppState { db_pool, .. }): State<AppState>,
+    headers: HeaderMap,
+    subscriber_id: Result<Path<Uuid>, PathRejection>,
+    patch: Result<Json<Map<String, Value>>, JsonRejection>,
+) -> Result<Json<Value>, AdminApiError> {
+    authenticate_basic(&headers, &db_pool, AdminApiError::AuthError).await?;
+    let Path(subscriber_id) = subscriber_id?;
+    let Json(patch) = patch?;
+
+    let (removed, set): (Vec<_>, Vec<_>) = patch.into_iter().partition(|(_, v)| v.is_null());
+    let removed: Vec<String> = removed.into_iter().map(|(key, _)| key).collect();
+    let set = Value::Object(set.into_iter().collect());
+    let row = sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET attributes = (attributes - $2::text[]) || $3
+    WHERE id = $1
+    RETURNING attributes
+        "#,
+        subscriber_id,
+        &removed,
+        set,
+    )
+    .fetch_optional(&db_pool)
+    .await
+    .context("Failed to update the subscriber's attributes")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+
+    Ok(Json(row.attributes))
+}
+
+async fn ensure_subscriber_exists(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), AdminApiError> {
+    sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up the subscriber")?
+    .ok_or(AdminApiError::NotFound(subscriber_id))?;
+
+    Ok(())
+}
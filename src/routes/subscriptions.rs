@@ -1,7 +1,12 @@
-use crate::{domain::NewSubscriber, email_client::EmailClient, startup::AppState};
+use crate::{
+    domain::NewSubscriber,
+    email_client::EmailTransport,
+    startup::AppState,
+    templates::{ConfirmationEmail, Templates},
+};
 use anyhow::Context;
 use axum::{Form, extract::State, http::StatusCode, response::IntoResponse};
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use rand::Rng;
 use serde::Deserialize;
 use sqlx::{Postgres, Transaction};
@@ -11,8 +16,14 @@ use uuid::Uuid;
 pub struct FormData {
     email: String,
     name: String,
+    /// The slug of the list to subscribe to. Forms that predate lists
+    /// subscribe to the default one.
+    list: Option<String>,
 }
 
+/// The list everyone subscribed to before there were several.
+pub const DEFAULT_LIST: &str = "newsletter";
+
 #[derive(thiserror::Error, Debug)]
 pub enum SubscribeError {
     #[error("{0}")]
@@ -45,9 +56,16 @@ pub async fn subscribe(
         db_pool,
         email_client,
         base_url,
+        templates,
+        preference_links,
+        ..
     }): State<AppState>,
     Form(form): Form<FormData>,
 ) -> Result<StatusCode, SubscribeError> {
+    let list_slug = form
+        .list
+        .clone()
+        .unwrap_or_else(|| DEFAULT_LIST.to_string());
     let subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
 
     let mut tx = db_pool
@@ -55,24 +73,75 @@ pub async fn subscribe(
         .await
         .context("Failed to begin a transaction")?;
 
-    let subscriber_id = insert_subscriber(&mut tx, &subscriber)
+    let list = get_list(&mut tx, &list_slug)
+        .await
+        .context("Failed to look up the list")?
+        .ok_or_else(|| SubscribeError::ValidationError(format!("Unknown list: {list_slug}")))?;
+    // Inserting first, rather than looking the address up first, keeps two
+    // concurrent sign-ups for a new address from racing on `UNIQUE(email)`.
+    let inserted = insert_subscriber(&mut tx, &subscriber)
         .await
         .context("Failed to insert new subscriber")?;
+    let subscriber_id = match inserted {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let existing = get_existing_subscriber(&mut tx, &subscriber)
+                .await
+                .context("Failed to look up an existing subscriber")?
+                .context("The existing subscriber disappeared")?;
+            let membership = get_membership_status(&mut tx, list.list_id, existing.id)
+                .await
+                .context("Failed to look up the list membership")?;
+            // Answer exactly as we would for a new address, so that the
+            // endpoint can't be used to find out who is on the list.
+            if existing.status == "confirmed" && membership.as_deref() == Some("confirmed") {
+                return Ok(StatusCode::OK);
+            }
+            // Mailing an address that bounced or complained again would hurt
+            // our sender reputation, so they stay off the list.
+            if existing.status == "bounced" || existing.status == "complained" {
+                return Ok(StatusCode::OK);
+            }
+            if existing.status == "unsubscribed" {
+                restart_double_opt_in(&mut tx, existing.id)
+                    .await
+                    .context("Failed to restart double opt-in")?;
+            }
+            existing.id
+        }
+    };
+    request_membership(&mut tx, list.list_id, subscriber_id)
+        .await
+        .context("Failed to store the list membership")?;
+    delete_tokens(&mut tx, subscriber_id, list.list_id)
+        .await
+        .context("Failed to delete previous subscription tokens")?;
     let subscription_token = generate_subscription_token();
-    store_token(&mut tx, subscriber_id, &subscription_token)
+    store_token(&mut tx, subscriber_id, list.list_id, &subscription_token)
         .await
         .context("Failed to store subscription token")?;
 
     tx.commit().await.context("Failed to commit transaction")?;
 
-    send_confirmation_email(&email_client, subscriber, &base_url.0, &subscription_token)
-        .await
-        .context("Failed to send confirmation email")?;
+    send_confirmation_email(
+        email_client.as_ref(),
+        &templates,
+        subscriber,
+        &list.name,
+        &base_url.0,
+        &subscription_token,
+        &preference_links.link(subscriber_id),
+    )
+    .await
+    .context("Failed to send confirmation email")?;
 
     Ok(StatusCode::OK)
 }
 
-fn generate_subscription_token() -> String {
+/// How long a confirmation link stays valid.
+const SUBSCRIPTION_TOKEN_TTL: TimeDelta = TimeDelta::hours(24);
+
+pub fn generate_subscription_token() -> String {
     let mut rng = rand::rng();
     std::iter::repeat_with(|| rng.sample(rand::distr::Alphanumeric))
         .map(char::from)
@@ -81,62 +150,200 @@ fn generate_subscription_token() -> String {
 }
 
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
+    templates: &Templates,
     new_subscriber: NewSubscriber,
+    list_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    preferences_url: &str,
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let body = templates.render_confirmation(&ConfirmationEmail {
+        name: new_subscriber.name.as_ref(),
+        list_name,
+        confirmation_link: &confirmation_link,
+        preferences_url,
+    })?;
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .send_email(&new_subscriber.email, "Welcome!", &body.html, &body.text)
+        .await?;
+    Ok(())
 }
 
-pub async fn insert_subscriber(
+pub struct List {
+    pub list_id: Uuid,
+    pub name: String,
+}
+
+pub async fn get_list(
+    tx: &mut Transaction<'_, Postgres>,
+    slug: &str,
+) -> Result<Option<List>, sqlx::Error> {
+    sqlx::query_as!(
+        List,
+        r#"SELECT list_id, name FROM lists WHERE slug = $1"#,
+        slug,
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+}
+
+async fn get_existing_subscriber(
     tx: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+/// Sends a subscriber who had left the list back to `pending_confirmation`.
+async fn restart_double_opt_in(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'pending_confirmation' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn get_membership_status(
+    tx: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT status FROM list_memberships
+    WHERE list_id = $1 AND subscriber_id = $2
+    FOR UPDATE
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
 
+    Ok(row.map(|r| r.status))
+}
+
+/// Adds a subscriber to a list, or brings them back to it, pending
+/// confirmation.
+pub async fn request_membership(
+    tx: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO list_memberships (list_id, subscriber_id, status)
+    VALUES ($1, $2, 'pending_confirmation')
+    ON CONFLICT (list_id, subscriber_id)
+    DO UPDATE SET status = 'pending_confirmation', updated_at = now()
         "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Invalidates the confirmation links a subscriber was sent for a list.
+pub async fn delete_tokens(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1 AND list_id = $2"#,
         subscriber_id,
+        list_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Invalidates every confirmation link a subscriber was sent, so that one
+/// cannot bring them back after they have left.
+pub async fn delete_subscriber_tokens(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Inserts the subscriber, unless the address is already taken, in which case
+/// it returns `None`.
+pub async fn insert_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+    VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+    ON CONFLICT (email) DO NOTHING
+    RETURNING id
+        "#,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        generate_subscription_token(),
     )
-    .execute(&mut **tx)
+    .fetch_optional(&mut **tx)
     .await?;
 
-    Ok(subscriber_id)
+    Ok(row.map(|r| r.id))
 }
 
 pub async fn store_token(
     tx: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
     sqlx::query!(
         r#"
-    INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-    VALUES ($1, $2)
+    INSERT INTO subscription_tokens
+        (subscription_token, subscriber_id, list_id, created_at, expires_at)
+    VALUES ($1, $2, $3, $4, $5)
         "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id,
+        now,
+        now + SUBSCRIPTION_TOKEN_TTL,
     )
     .execute(&mut **tx)
     .await?;
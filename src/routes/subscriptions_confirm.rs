@@ -1,12 +1,18 @@
-use crate::startup::AppState;
+use crate::{
+    domain::NewSubscriber,
+    routes::{delete_tokens, generate_subscription_token, send_confirmation_email, store_token},
+    startup::AppState,
+};
 use anyhow::Context;
 use axum::{
+    Form,
     extract::{Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{Html, IntoResponse},
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(Deserialize)]
@@ -20,15 +26,37 @@ pub enum ConfirmationError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken(String),
+    #[error("The confirmation link has already been used.")]
+    ConsumedToken,
+    #[error("This subscription can no longer be confirmed.")]
+    InactiveSubscriber,
 }
 
 impl IntoResponse for ConfirmationError {
     fn into_response(self) -> axum::response::Response {
+        tracing::error!("Failed to confirm subscription: {}", self);
         let status_code = match self {
             ConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ConfirmationError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmationError::ConsumedToken | ConfirmationError::InactiveSubscriber => {
+                StatusCode::CONFLICT
+            }
+            ConfirmationError::ExpiredToken(ref token) => {
+                // Offer a fresh link right away rather than a dead end.
+                let body = format!(
+                    r#"<p>{}</p>
+<form action="/subscriptions/confirm/resend" method="post">
+    <input type="hidden" name="subscription_token" value="{}">
+    <button type="submit">Send me a new link</button>
+</form>"#,
+                    self,
+                    htmlescape::encode_attribute(token),
+                );
+                return (StatusCode::GONE, Html(body)).into_response();
+            }
         };
-        tracing::error!("Failed to confirm subscription: {}", self);
         (status_code, self.to_string()).into_response()
     }
 }
@@ -37,39 +65,198 @@ pub async fn confirm(
     Query(params): Query<Parameters>,
     State(AppState { db_pool, .. }): State<AppState>,
 ) -> Result<StatusCode, ConfirmationError> {
-    let subscriber_id = get_subscriber_id_from_token(&db_pool, &params.subscription_token)
+    let mut tx = db_pool
+        .begin()
         .await
-        .context("Failed to retrieve subscriber id from token")?
+        .context("Failed to begin a transaction")?;
+    let token = get_token(&mut tx, &params.subscription_token)
+        .await
+        .context("Failed to retrieve subscription token")?
         .ok_or(ConfirmationError::UnknownToken)?;
+    if token.consumed_at.is_some() {
+        return Err(ConfirmationError::ConsumedToken);
+    }
+    if token.expires_at <= Utc::now() {
+        return Err(ConfirmationError::ExpiredToken(params.subscription_token));
+    }
 
-    confirm_subscriber(&db_pool, subscriber_id)
+    let confirmed = confirm_subscriber(&mut tx, token.subscriber_id, token.list_id)
         .await
         .context("Failed to confirm subscriber")?;
+    if !confirmed {
+        return Err(ConfirmationError::InactiveSubscriber);
+    }
+    consume_token(&mut tx, &params.subscription_token)
+        .await
+        .context("Failed to mark the subscription token as consumed")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Sends a new confirmation link in exchange for a previous one, typically
+/// after it has expired. Links for subscriptions that have already been
+/// confirmed are accepted but ignored.
+pub async fn resend_confirmation(
+    State(AppState {
+        db_pool,
+        email_client,
+        base_url,
+        templates,
+        preference_links,
+        ..
+    }): State<AppState>,
+    Form(params): Form<Parameters>,
+) -> Result<StatusCode, ConfirmationError> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let token = get_token(&mut tx, &params.subscription_token)
+        .await
+        .context("Failed to retrieve subscription token")?
+        .ok_or(ConfirmationError::UnknownToken)?;
+    let pending = get_pending_subscription(&mut tx, token.subscriber_id, token.list_id)
+        .await
+        .context("Failed to retrieve subscriber")?;
+    let Some((subscriber, list_name)) = pending else {
+        return Ok(StatusCode::OK);
+    };
+
+    delete_tokens(&mut tx, token.subscriber_id, token.list_id)
+        .await
+        .context("Failed to delete previous subscription tokens")?;
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut tx,
+        token.subscriber_id,
+        token.list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store subscription token")?;
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    send_confirmation_email(
+        email_client.as_ref(),
+        &templates,
+        subscriber,
+        &list_name,
+        &base_url.0,
+        &subscription_token,
+        &preference_links.link(token.subscriber_id),
+    )
+    .await
+    .context("Failed to send confirmation email")?;
 
     Ok(StatusCode::OK)
 }
 
-async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), anyhow::Error> {
+struct SubscriptionToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+async fn get_token(
+    tx: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<Option<SubscriptionToken>, anyhow::Error> {
+    let token = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT subscriber_id, list_id, expires_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        FOR UPDATE
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(token)
+}
+
+/// Confirms the subscriber's address along with their membership of the list
+/// the link was sent for.
+/// Returns `false`, leaving everything untouched, if the subscriber has since
+/// unsubscribed, bounced or complained. Confirmed subscribers still go through:
+/// they are joining another list.
+async fn confirm_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed', updated_at = now()
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
         subscriber_id,
+        list_id,
     )
-    .execute(db_pool)
+    .execute(&mut **tx)
     .await?;
 
-    Ok(())
+    Ok(true)
 }
 
-async fn get_subscriber_id_from_token(
-    db_pool: &PgPool,
+async fn consume_token(
+    tx: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, anyhow::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET consumed_at = now()
+        WHERE subscription_token = $1
+        "#,
         subscription_token,
     )
-    .fetch_optional(db_pool)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// The subscriber behind a membership still awaiting confirmation, and the
+/// name of the list.
+async fn get_pending_subscription(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<Option<(NewSubscriber, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, l.name AS list_name
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+            AND m.list_id = $2
+            AND m.status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .fetch_optional(&mut **tx)
     .await?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    row.map(|r| Ok((NewSubscriber::new(r.email, r.name)?, r.list_name)))
+        .transpose()
 }
@@ -0,0 +1,133 @@
+use crate::routes::{delete_subscriber_tokens, record_preference_change};
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> axum::response::Response {
+        let status_code = match self {
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+        };
+        tracing::error!("Failed to unsubscribe: {}", self);
+        (status_code, self.to_string()).into_response()
+    }
+}
+
+/// The link in the newsletter footer. It only asks for confirmation: mail
+/// scanners follow links to check them, and must not unsubscribe anyone.
+pub async fn unsubscribe_form(
+    Query(params): Query<UnsubscribeParameters>,
+    State(AppState { db_pool, .. }): State<AppState>,
+) -> Result<Html<String>, UnsubscribeError> {
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE unsubscribe_token = $1"#,
+        params.token,
+    )
+    .fetch_optional(&db_pool)
+    .await
+    .context("Failed to look up the subscriber")?
+    .ok_or(UnsubscribeError::UnknownToken)?;
+
+    Ok(Html(format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Stop sending issues to {email}?</p>
+    <form action="/subscriptions/unsubscribe?token={token}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+        email = htmlescape::encode_minimal(&subscriber.email),
+        token = htmlescape::encode_attribute(&params.token),
+    )))
+}
+
+/// Serves both the form behind the footer link and RFC 8058 one-click
+/// unsubscribe requests sent by mail clients.
+pub async fn unsubscribe(
+    Query(params): Query<UnsubscribeParameters>,
+    State(AppState { db_pool, .. }): State<AppState>,
+) -> Result<Html<&'static str>, UnsubscribeError> {
+    let unsubscribed = unsubscribe_subscriber(&db_pool, &params.token)
+        .await
+        .context("Failed to unsubscribe subscriber")?;
+    if !unsubscribed {
+        return Err(UnsubscribeError::UnknownToken);
+    }
+
+    Ok(Html(
+        "<p>You have been unsubscribed. You will not receive any further issues.</p>",
+    ))
+}
+
+/// Takes the subscriber off every list they are on.
+async fn unsubscribe_subscriber(
+    db_pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut tx = db_pool.begin().await?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE unsubscribe_token = $1 FOR UPDATE"#,
+        unsubscribe_token,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(subscriber) = subscriber else {
+        return Ok(false);
+    };
+    if subscriber.status != "unsubscribed" {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+            subscriber.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        record_preference_change(
+            &mut tx,
+            subscriber.id,
+            "status",
+            Some(&subscriber.status),
+            Some("unsubscribed"),
+        )
+        .await?;
+    }
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed', updated_at = now()
+        WHERE subscriber_id = $1
+        "#,
+        subscriber.id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    delete_subscriber_tokens(&mut tx, subscriber.id).await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
@@ -0,0 +1,128 @@
+use crate::startup::AppState;
+use crate::tracking::{TrackingKind, TrackingToken};
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrackingError {
+    #[error("There is nothing at this address.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for TrackingError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            TrackingError::NotFound => StatusCode::NOT_FOUND,
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if let TrackingError::UnexpectedError(e) = &self {
+            tracing::error!(error.cause_chain = ?e, "Failed to record a tracking event");
+        }
+        (status_code, self.to_string()).into_response()
+    }
+}
+
+/// Serves the open pixel and records that the issue was opened.
+pub async fn track_open(
+    State(AppState {
+        db_pool, tracker, ..
+    }): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, TrackingError> {
+    let token = tracker.verify(&token).ok_or(TrackingError::NotFound)?;
+    if token.kind != TrackingKind::Open {
+        return Err(TrackingError::NotFound);
+    }
+    record_event(&db_pool, &token, "open", None)
+        .await
+        .context("Failed to record an open")?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/gif"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        PIXEL,
+    )
+        .into_response())
+}
+
+/// Records a click and sends the reader on to the link in the issue.
+pub async fn track_click(
+    State(AppState {
+        db_pool, tracker, ..
+    }): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Redirect, TrackingError> {
+    let token = tracker.verify(&token).ok_or(TrackingError::NotFound)?;
+    let TrackingKind::Click { link_index } = token.kind else {
+        return Err(TrackingError::NotFound);
+    };
+    // The destination comes from the issue, never from the request.
+    let url = get_link(&db_pool, token.newsletter_issue_id, link_index)
+        .await
+        .context("Failed to look up a tracked link")?
+        .ok_or(TrackingError::NotFound)?;
+    record_event(&db_pool, &token, "click", Some(link_index))
+        .await
+        .context("Failed to record a click")?;
+
+    Ok(Redirect::to(&url))
+}
+
+async fn get_link(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    link_index: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT url FROM issue_links
+        WHERE newsletter_issue_id = $1 AND link_index = $2
+        "#,
+        newsletter_issue_id,
+        link_index,
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+async fn record_event(
+    db_pool: &PgPool,
+    token: &TrackingToken,
+    kind: &str,
+    link_index: Option<i32>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (
+            tracking_event_id, newsletter_issue_id, subscriber_id, kind, link_index
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        token.newsletter_issue_id,
+        token.subscriber_id,
+        kind,
+        link_index,
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::authentication::basic_authentication;
+use crate::config::WebhookConfig;
+use crate::routes::delete_subscriber_tokens;
+use crate::startup::AppState;
+use anyhow::Context;
+use axum::Json;
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::{IntoResponse, Response};
+use axum::{extract::State, http::StatusCode};
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use sqlx::{Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+/// The fields of a Postmark bounce or spam complaint payload that we act on.
+/// The full payload is stored as is.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EmailEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    kind: Option<String>,
+    email: Option<String>,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let body = format!("{:?}", self);
+        match self {
+            WebhookError::AuthError(_) => (
+                StatusCode::UNAUTHORIZED,
+                [(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="webhooks""#),
+                )],
+                body,
+            )
+                .into_response(),
+            WebhookError::ValidationError(_) => (StatusCode::BAD_REQUEST, body).into_response(),
+            WebhookError::UnexpectedError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        }
+    }
+}
+
+/// Receives delivery events from the email provider. Hard bounces and spam
+/// complaints take the subscriber off the list; every event is kept for audit.
+pub async fn receive_email_event(
+    State(AppState {
+        db_pool,
+        webhook_credentials,
+        ..
+    }): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<StatusCode, WebhookError> {
+    authenticate(&headers, &webhook_credentials)?;
+    let event: EmailEvent = serde_json::from_value(payload.clone())
+        .map_err(|e| WebhookError::ValidationError(e.to_string()))?;
+    tracing::info!(?event, "Received an email event");
+
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    store_event(&mut tx, &event, &payload)
+        .await
+        .context("Failed to store the email event")?;
+
+    match (event.record_type.as_str(), event.kind.as_deref()) {
+        ("Bounce", Some("HardBounce")) => {
+            if let Some(email) = &event.email {
+                update_subscriber_status(&mut tx, email, "bounced")
+                    .await
+                    .context("Failed to mark the subscriber as bounced")?;
+            }
+            if let Some(message_id) = &event.message_id {
+                mark_delivery_as_bounced(&mut tx, message_id)
+                    .await
+                    .context("Failed to mark the delivery as bounced")?;
+            }
+        }
+        ("SpamComplaint", _) => {
+            if let Some(email) = &event.email {
+                update_subscriber_status(&mut tx, email, "complained")
+                    .await
+                    .context("Failed to mark the subscriber as complained")?;
+            }
+        }
+        // Soft bounces and other events are only kept for audit.
+        _ => {}
+    }
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok(StatusCode::OK)
+}
+
+fn authenticate(headers: &HeaderMap, expected: &WebhookConfig) -> Result<(), WebhookError> {
+    let credentials = basic_authentication(headers).map_err(WebhookError::AuthError)?;
+    let username_matches = credentials
+        .username
+        .as_bytes()
+        .ct_eq(expected.username.as_bytes());
+    let password_matches = credentials
+        .password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(expected.password.expose_secret().as_bytes());
+    if !bool::from(username_matches & password_matches) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials"
+        )));
+    }
+
+    Ok(())
+}
+
+async fn store_event(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+    payload: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO email_events (
+        email_event_id, record_type, email, provider_message_id, payload, received_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        event.record_type,
+        event.email,
+        event.message_id,
+        payload,
+        Utc::now(),
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn update_subscriber_status(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let subscribers = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE lower(email) = lower($1) RETURNING id"#,
+        email,
+        status,
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    for subscriber in subscribers {
+        delete_subscriber_tokens(tx, subscriber.id).await?;
+    }
+
+    Ok(())
+}
+
+async fn mark_delivery_as_bounced(
+    tx: &mut Transaction<'_, Postgres>,
+    provider_message_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    UPDATE deliveries
+    SET status = 'bounced', updated_at = now()
+    WHERE provider_message_id = $1
+        "#,
+        provider_message_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,494 @@
+//! Segment expressions, picking out a subset of subscribers.
+//!
+//! ```text
+//! tag:beta AND NOT tag:churned AND subscribed_at > 2025-01-01
+//! (attributes.plan = "pro" OR attributes.seats >= 10) AND NOT tag:trial
+//! ```
+//!
+//! A segment is made of conditions combined with `AND`, `OR`, `NOT` and
+//! parentheses. `NOT` binds tighter than `AND`, which binds tighter than `OR`.
+//! The conditions are:
+//!
+//! - `tag:<tag>`: the subscriber carries the tag.
+//! - `subscribed_at <op> <YYYY-MM-DD>`: compares the subscription date with
+//!   midnight UTC on that day.
+//! - `attributes.<key> <op> <value>`: compares a custom attribute. Unquoted
+//!   numbers compare numerically with attributes holding a number; anything
+//!   else compares as text.
+//!
+//! where `<op>` is one of `=`, `!=`, `<`, `<=`, `>` and `>=`.
+//!
+//! Segments compile to a SQL condition on the `subscriptions` table aliased as
+//! `s`. Every value is bound as a parameter.
+
+use crate::domain::SubscriberTag;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{Postgres, QueryBuilder};
+
+/// How deeply parentheses and `NOT` may nest.
+const MAX_DEPTH: usize = 32;
+/// How many conditions a segment may hold. `AND` and `OR` chains build a
+/// tree as deep as they are long, and parsing, compiling and dropping a
+/// segment all recurse over it: together with `MAX_DEPTH`, this keeps that
+/// recursion shallow whatever the input.
+const MAX_CONDITIONS: usize = 256;
+
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    SubscribedAt(Comparison, DateTime<Utc>),
+    Attribute {
+        key: String,
+        comparison: Comparison,
+        value: AttributeValue,
+    },
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AttributeValue {
+    Number(f64),
+    Text(String),
+}
+
+impl Comparison {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::NotEq => "<>",
+            Comparison::Lt => "<",
+            Comparison::LtEq => "<=",
+            Comparison::Gt => ">",
+            Comparison::GtEq => ">=",
+        }
+    }
+}
+
+impl Segment {
+    pub fn parse(input: &str) -> Result<Self, anyhow::Error> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            anyhow::bail!("The segment is empty");
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            conditions: 0,
+        };
+        let segment = parser.or(0)?;
+        if let Some(token) = parser.peek() {
+            anyhow::bail!("Unexpected {} in the segment", token.describe());
+        }
+        Ok(segment)
+    }
+
+    /// Appends the segment as a SQL condition on subscribers aliased as `s`.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                query.push(
+                    "EXISTS (SELECT 1 FROM subscriber_tags AS t \
+                     WHERE t.subscriber_id = s.id AND t.tag = ",
+                );
+                query.push_bind(tag.as_ref().to_owned());
+                query.push(")");
+            }
+            Segment::SubscribedAt(comparison, date) => {
+                query.push(format_args!("s.subscribed_at {} ", comparison.as_sql()));
+                query.push_bind(*date);
+            }
+            Segment::Attribute {
+                key,
+                comparison,
+                value: AttributeValue::Number(number),
+            } => {
+                // The cast only runs on attributes holding a number; any other
+                // attribute compares as NULL and doesn't match.
+                query.push("(CASE WHEN jsonb_typeof(s.attributes -> ");
+                query.push_bind(key.clone());
+                query.push(") = 'number' THEN (s.attributes ->> ");
+                query.push_bind(key.clone());
+                query.push(format_args!(")::float8 END) {} ", comparison.as_sql()));
+                query.push_bind(*number);
+            }
+            Segment::Attribute {
+                key,
+                comparison,
+                value: AttributeValue::Text(text),
+            } => {
+                query.push("(s.attributes ->> ");
+                query.push_bind(key.clone());
+                query.push(format_args!(") {} ", comparison.as_sql()));
+                query.push_bind(text.clone());
+            }
+            Segment::Not(inner) => {
+                // A condition on a missing attribute is NULL rather than false;
+                // NOT must still pick those subscribers.
+                query.push("(");
+                inner.push_sql(query);
+                query.push(") IS NOT TRUE");
+            }
+            Segment::And(left, right) => push_binary(query, left, "AND", right),
+            Segment::Or(left, right) => push_binary(query, left, "OR", right),
+        }
+    }
+}
+
+fn push_binary(
+    query: &mut QueryBuilder<'_, Postgres>,
+    left: &Segment,
+    operator: &str,
+    right: &Segment,
+) {
+    query.push("(");
+    left.push_sql(query);
+    query.push(format_args!(") {operator} ("));
+    right.push_sql(query);
+    query.push(")");
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Comparison(Comparison),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("`{word}`"),
+            Token::Quoted(text) => format!("\"{text}\""),
+            Token::Comparison(comparison) => format!("`{}`", comparison.as_sql()),
+            Token::Open => "`(`".into(),
+            Token::Close => "`)`".into(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, anyhow::Error> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Comparison(match (c, or_equal) {
+                    ('=', false) => Comparison::Eq,
+                    ('!', true) => Comparison::NotEq,
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::LtEq,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::GtEq,
+                    _ => anyhow::bail!("Unknown operator in the segment; use != for not equal"),
+                }));
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => text.push(escaped),
+                            None => anyhow::bail!("Unterminated string in the segment"),
+                        },
+                        Some(c) => text.push(c),
+                        None => anyhow::bail!("Unterminated string in the segment"),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()=!<>\"".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser, one method per precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    conditions: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, anyhow::Error> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| anyhow::anyhow!("The segment ends unexpectedly"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self, depth: usize) -> Result<Segment, anyhow::Error> {
+        let mut segment = self.and(depth)?;
+        while self.eat_keyword("OR") {
+            segment = Segment::Or(Box::new(segment), Box::new(self.and(depth)?));
+        }
+        Ok(segment)
+    }
+
+    fn and(&mut self, depth: usize) -> Result<Segment, anyhow::Error> {
+        let mut segment = self.not(depth)?;
+        while self.eat_keyword("AND") {
+            segment = Segment::And(Box::new(segment), Box::new(self.not(depth)?));
+        }
+        Ok(segment)
+    }
+
+    fn not(&mut self, depth: usize) -> Result<Segment, anyhow::Error> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("The segment is nested too deeply");
+        }
+        if self.eat_keyword("NOT") {
+            return Ok(Segment::Not(Box::new(self.not(depth + 1)?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let segment = self.or(depth + 1)?;
+            if self.next()? != &Token::Close {
+                anyhow::bail!("Expected `)` in the segment");
+            }
+            return Ok(segment);
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Segment, anyhow::Error> {
+        self.conditions += 1;
+        if self.conditions > MAX_CONDITIONS {
+            anyhow::bail!("A segment cannot have more than {MAX_CONDITIONS} conditions");
+        }
+        let field = match self.next()? {
+            Token::Word(word) => word.clone(),
+            token => anyhow::bail!("Expected a condition, found {}", token.describe()),
+        };
+        if let Some(tag) = field.strip_prefix("tag:") {
+            return Ok(Segment::Tag(SubscriberTag::parse(tag)?));
+        }
+
+        let comparison = match self.next()? {
+            Token::Comparison(comparison) => *comparison,
+            token => anyhow::bail!(
+                "Expected an operator after `{field}`, found {}",
+                token.describe()
+            ),
+        };
+        let (value, quoted) = match self.next()? {
+            Token::Word(word) => (word.clone(), false),
+            Token::Quoted(text) => (text.clone(), true),
+            token => anyhow::bail!(
+                "Expected a value after `{field}`, found {}",
+                token.describe()
+            ),
+        };
+
+        if field == "subscribed_at" {
+            let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d").map_err(|_| {
+                anyhow::anyhow!("`subscribed_at` compares with a date such as 2025-01-31")
+            })?;
+            return Ok(Segment::SubscribedAt(
+                comparison,
+                date.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+            ));
+        }
+        if let Some(key) = field.strip_prefix("attributes.") {
+            if key.is_empty() {
+                anyhow::bail!("Missing attribute name after `attributes.`");
+            }
+            let value = match value.parse::<f64>() {
+                Ok(number) if !quoted && number.is_finite() => AttributeValue::Number(number),
+                _ => AttributeValue::Text(value),
+            };
+            return Ok(Segment::Attribute {
+                key: key.to_owned(),
+                comparison,
+                value,
+            });
+        }
+        anyhow::bail!("Unknown field `{field}`; use tag:<tag>, subscribed_at or attributes.<name>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeValue, Comparison, Segment};
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+    use sqlx::QueryBuilder;
+
+    fn tag(tag: &str) -> Box<Segment> {
+        Box::new(Segment::Tag(SubscriberTag::parse(tag).unwrap()))
+    }
+
+    fn sql(segment: &str) -> String {
+        let mut query = QueryBuilder::new("");
+        Segment::parse(segment).unwrap().push_sql(&mut query);
+        query.sql().to_owned()
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        let segment = Segment::parse("tag:a OR NOT tag:b AND tag:c").unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::Or(
+                tag("a"),
+                Box::new(Segment::And(Box::new(Segment::Not(tag("b"))), tag("c")))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_group_conditions() {
+        let segment = Segment::parse("(tag:a or tag:b) and tag:c").unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::And(Box::new(Segment::Or(tag("a"), tag("b"))), tag("c"))
+        );
+    }
+
+    #[test]
+    fn dates_and_attributes_are_parsed() {
+        let segment = Segment::parse(
+            r#"subscribed_at>2025-01-01 AND attributes.seats >= 10 AND attributes.plan != "pro plan""#,
+        )
+        .unwrap();
+
+        let Segment::And(left, plan) = segment else {
+            panic!("Expected a conjunction, got {segment:?}");
+        };
+        let Segment::And(subscribed_at, seats) = *left else {
+            panic!("Expected a conjunction, got {left:?}");
+        };
+        assert_eq!(
+            *subscribed_at,
+            Segment::SubscribedAt(Comparison::Gt, "2025-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            *seats,
+            Segment::Attribute {
+                key: "seats".into(),
+                comparison: Comparison::GtEq,
+                value: AttributeValue::Number(10.0),
+            }
+        );
+        assert_eq!(
+            *plan,
+            Segment::Attribute {
+                key: "plan".into(),
+                comparison: Comparison::NotEq,
+                value: AttributeValue::Text("pro plan".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn quoted_numbers_compare_as_text() {
+        let segment = Segment::parse(r#"attributes.zip = "01234""#).unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::Attribute {
+                key: "zip".into(),
+                comparison: Comparison::Eq,
+                value: AttributeValue::Text("01234".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn values_are_bound_rather_than_inlined() {
+        assert_eq!(
+            sql("tag:beta AND NOT attributes.plan = 'x'"),
+            "(EXISTS (SELECT 1 FROM subscriber_tags AS t WHERE t.subscriber_id = s.id \
+             AND t.tag = $1)) AND (((s.attributes ->> $2) = $3) IS NOT TRUE)"
+        );
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for segment in [
+            "",
+            "tag:",
+            "tag:beta AND",
+            "tag:beta tag:alpha",
+            "(tag:beta",
+            "tag:beta)",
+            "subscribed_at > yesterday",
+            "subscribed_at 2025-01-01",
+            "email = a@example.com",
+            "attributes. = 1",
+            "attributes.plan = \"pro",
+            "attributes.plan =! pro",
+        ] {
+            assert_err!(Segment::parse(segment), "{segment} was accepted");
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let segment = format!("{}tag:a{}", "(".repeat(100), ")".repeat(100));
+        assert_err!(Segment::parse(&segment));
+        let segment = format!("{}tag:a", "NOT ".repeat(100));
+        assert_err!(Segment::parse(&segment));
+    }
+
+    #[test]
+    fn long_chains_of_conditions_are_rejected() {
+        let chain = |n: usize, operator: &str| vec!["tag:a"; n].join(operator);
+        assert_ok!(Segment::parse(&chain(256, " AND ")));
+        assert_err!(Segment::parse(&chain(257, " AND ")));
+        assert_err!(Segment::parse(&chain(50_000, " OR ")));
+    }
+}
@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tower_sessions::{
+    SessionStore,
+    cookie::time::OffsetDateTime,
+    session::{Id, Record},
+    session_store,
+};
+
+/// Keeps session records in the `sessions` table, so that they survive
+/// restarts and are shared between instances.
+#[derive(Clone, Debug)]
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PgSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let data = encode(record)?;
+        let expiry_date = to_chrono(record.expiry_date);
+        loop {
+            let inserted = sqlx::query!(
+                r#"
+        INSERT INTO sessions (id, data, expiry_date)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+                "#,
+                record.id.to_string(),
+                data,
+                expiry_date,
+            )
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?
+            .rows_affected();
+            if inserted > 0 {
+                return Ok(());
+            }
+            // The id is already taken, try again with a fresh one.
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        sqlx::query!(
+            r#"
+        INSERT INTO sessions (id, data, expiry_date)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE
+        SET data = excluded.data, expiry_date = excluded.expiry_date
+            "#,
+            record.id.to_string(),
+            encode(record)?,
+            to_chrono(record.expiry_date),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query!(
+            r#"SELECT data, expiry_date FROM sessions WHERE id = $1 AND expiry_date > now()"#,
+            session_id.to_string(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend)?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let expiry_date = OffsetDateTime::from_unix_timestamp(row.expiry_date.timestamp())
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+
+        Ok(Some(Record {
+            id: *session_id,
+            data: serde_json::from_slice(&row.data)
+                .map_err(|e| session_store::Error::Decode(e.to_string()))?,
+            expiry_date,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE id = $1"#,
+            session_id.to_string()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend)?;
+
+        Ok(())
+    }
+}
+
+fn encode(record: &Record) -> session_store::Result<Vec<u8>> {
+    serde_json::to_vec(&record.data).map_err(|e| session_store::Error::Encode(e.to_string()))
+}
+
+fn to_chrono(expiry_date: OffsetDateTime) -> DateTime<Utc> {
+    DateTime::from_timestamp(expiry_date.unix_timestamp(), 0).unwrap_or_default()
+}
+
+fn backend(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
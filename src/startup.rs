@@ -1,14 +1,33 @@
 use crate::{
-    config::AppBaseUrl,
-    email_client::EmailClient,
-    routes::{check_health, confirm, publish_newsletter, subscribe},
+    authentication::reject_anonymous_users,
+    config::{AppBaseUrl, WebhookConfig},
+    email_client::EmailTransport,
+    preferences::PreferenceLinks,
+    routes::{
+        add_subscriber_tag, admin_dashboard, archived_issue, cancel_newsletter, change_password,
+        change_password_form, check_health, confirm, create_draft, delete_draft, delete_subscriber,
+        get_newsletter, get_subscriber, get_subscriber_attributes, get_subscriber_tags,
+        import_subscribers_from_csv, list_newsletters, list_subscribers, log_out, login,
+        login_form, preferences_form, preview_newsletter, publish_newsletter, receive_email_event,
+        remove_subscriber_tag, resend_confirmation, resume_newsletter, schedule_newsletter,
+        send_test_newsletter, subscribe, track_click, track_open, unsubscribe, unsubscribe_form,
+        unsubscribe_from_preferences, update_draft, update_preferences, update_subscriber,
+        update_subscriber_attributes,
+    },
+    session_store::PgSessionStore,
+    templates::Templates,
+    tracking::Tracker,
 };
+use anyhow::Context;
 use axum::{
     Router,
     http::{HeaderName, Request},
-    routing::{get, post},
+    middleware,
+    routing::{get, post, put},
     serve::Serve,
 };
+use axum_messages::MessagesManagerLayer;
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 use std::sync::Arc;
 use tokio::net::TcpListener;
@@ -17,6 +36,7 @@ use tower_http::{
     request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     trace::TraceLayer,
 };
+use tower_sessions::{SessionManagerLayer, cookie::Key};
 use tracing::{error, info_span};
 
 const REQUEST_ID_HEADER: &str = "x-request-id";
@@ -24,33 +44,103 @@ const REQUEST_ID_HEADER: &str = "x-request-id";
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: PgPool,
-    pub email_client: Arc<EmailClient>,
+    pub email_client: Arc<dyn EmailTransport>,
     pub base_url: AppBaseUrl,
+    pub webhook_credentials: WebhookConfig,
+    pub test_subject_prefix: String,
+    pub templates: Arc<Templates>,
+    pub tracker: Arc<Tracker>,
+    pub preference_links: Arc<PreferenceLinks>,
 }
 
 pub async fn serve(
     listener: TcpListener,
-    pool: PgPool,
-    email_client: EmailClient,
-    base_url: AppBaseUrl,
+    app_state: AppState,
+    hmac_secret: SecretString,
 ) -> Result<Serve<TcpListener, Router, Router>, anyhow::Error> {
-    let app_state = AppState {
-        db_pool: pool.clone(),
-        email_client: Arc::new(email_client),
-        base_url,
-    };
+    let pool = app_state.db_pool.clone();
+    let admin = Router::new()
+        .route("/dashboard", get(admin_dashboard))
+        .route("/password", get(change_password_form).post(change_password))
+        .route("/logout", post(log_out))
+        .layer(middleware::from_fn(reject_anonymous_users));
     let app = Router::new()
         .route("/health", get(check_health))
+        .route("/login", get(login_form).post(login))
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions/confirm", get(confirm))
-        .route("/newsletters", post(publish_newsletter))
+        .route("/subscriptions/confirm/resend", post(resend_confirmation))
+        .route(
+            "/subscriptions/unsubscribe",
+            get(unsubscribe_form).post(unsubscribe),
+        )
+        .route(
+            "/newsletters",
+            get(list_newsletters).post(publish_newsletter),
+        )
+        .route("/newsletters/drafts", post(create_draft))
+        .route(
+            "/newsletters/{id}",
+            get(get_newsletter).put(update_draft).delete(delete_draft),
+        )
+        .route("/newsletters/{id}/preview", get(preview_newsletter))
+        .route("/newsletters/{id}/schedule", post(schedule_newsletter))
+        .route("/newsletters/{id}/cancel", post(cancel_newsletter))
+        .route("/newsletters/{id}/test", post(send_test_newsletter))
+        .route("/webhooks/email", post(receive_email_event))
+        .route("/newsletters/{id}/resume", post(resume_newsletter))
+        .route("/archive/{slug}", get(archived_issue))
+        .route(
+            "/preferences/{token}",
+            get(preferences_form).post(update_preferences),
+        )
+        .route(
+            "/preferences/{token}/unsubscribe",
+            post(unsubscribe_from_preferences),
+        )
+        .route("/t/o/{token}", get(track_open))
+        .route("/t/c/{token}", get(track_click))
+        .route("/admin/api/subscribers", get(list_subscribers))
+        .route(
+            "/admin/api/subscribers/import",
+            post(import_subscribers_from_csv),
+        )
+        .route(
+            "/admin/api/subscribers/{id}",
+            get(get_subscriber)
+                .patch(update_subscriber)
+                .delete(delete_subscriber),
+        )
+        .route("/admin/api/subscribers/{id}/tags", get(get_subscriber_tags))
+        .route(
+            "/admin/api/subscribers/{id}/tags/{tag}",
+            put(add_subscriber_tag).delete(remove_subscriber_tag),
+        )
+        .route(
+            "/admin/api/subscribers/{id}/attributes",
+            get(get_subscriber_attributes).patch(update_subscriber_attributes),
+        )
+        .nest("/admin", admin)
         .with_state(app_state);
 
+    let app = add_session(app, pool, &hmac_secret)?;
     let app = add_tracing(app);
 
     Ok(axum::serve(listener, app))
 }
 
+pub fn add_session(
+    app: Router,
+    pool: PgPool,
+    hmac_secret: &SecretString,
+) -> Result<Router, anyhow::Error> {
+    let key = Key::try_from(hmac_secret.expose_secret().as_bytes())
+        .context("The HMAC secret must be at least 64 bytes long")?;
+    let session_layer = SessionManagerLayer::new(PgSessionStore::new(pool)).with_signed(key);
+
+    Ok(app.layer(MessagesManagerLayer).layer(session_layer))
+}
+
 pub fn add_tracing(app: Router) -> Router {
     let x_request_id = HeaderName::from_static(REQUEST_ID_HEADER);
 
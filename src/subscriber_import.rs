@@ -0,0 +1,397 @@
+//! Bulk imports of subscribers from CSV, shared by the admin API and the
+//! `import-subscribers` command.
+//!
+//! The CSV is parsed on a blocking thread and handed over one row at a time
+//! through a bounded channel, so an import never holds more than a handful of
+//! rows in memory however large the file is. Each row is committed on its own:
+//! if the import is interrupted, running it again reports the rows that made
+//! it in as duplicates.
+//!
+//! Confirmation emails are only queued, along with the row: the
+//! [confirmation email worker](crate::confirmation_email_worker) sends them.
+use crate::confirmation_email_worker::enqueue_confirmation_email;
+use crate::domain::NewSubscriber;
+use crate::routes::{List, generate_subscription_token, get_list, request_membership, store_token};
+use anyhow::{Context, anyhow};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::io::Read;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// How many parsed rows may wait for the database at any one time.
+const ROW_BUFFER: usize = 64;
+
+/// The status imported subscribers start with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    /// They already opted in elsewhere and start receiving issues right away.
+    Confirmed,
+    /// They have to confirm their address before they get anything.
+    PendingConfirmation,
+}
+
+impl ImportMode {
+    pub const ALL: [ImportMode; 2] = [ImportMode::Confirmed, ImportMode::PendingConfirmation];
+
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| anyhow!("Unknown import mode: {s}"))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::PendingConfirmation => "pending_confirmation",
+        }
+    }
+}
+
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// The slug of the list to add everyone to.
+    pub list: String,
+    /// Whether to queue a confirmation email for each pending subscriber.
+    pub send_confirmations: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    /// The import as a whole cannot go ahead: bad options or a CSV without
+    /// the expected columns. Nothing has been imported.
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOutcome {
+    Accepted,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct RowReport {
+    /// The line the row starts on, counting the header as line 1.
+    pub line: u64,
+    pub email: Option<String>,
+    pub outcome: RowOutcome,
+    /// Why the row was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub accepted: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub rows: Vec<RowReport>,
+}
+
+impl ImportReport {
+    fn push(&mut self, row: RowReport) {
+        match row.outcome {
+            RowOutcome::Accepted => self.accepted += 1,
+            RowOutcome::Duplicate => self.duplicates += 1,
+            RowOutcome::Invalid => self.invalid += 1,
+        }
+        self.rows.push(row);
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CsvRow {
+    email: String,
+    name: String,
+}
+
+/// A row as it comes out of the CSV reader, not yet validated.
+struct ParsedRow {
+    line: u64,
+    row: Result<CsvRow, String>,
+}
+
+/// Imports the subscribers in `input`, a CSV with a header row that has (at
+/// least) `email` and `name` columns.
+///
+/// Rows are validated with [`NewSubscriber::new`]. Addresses we already know
+/// about, in any status, are reported as duplicates and left untouched.
+pub async fn import_subscribers<R>(
+    db_pool: &PgPool,
+    input: R,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError>
+where
+    R: Read + Send + 'static,
+{
+    if options.send_confirmations && options.mode == ImportMode::Confirmed {
+        return Err(ImportError::InvalidInput(
+            "Confirmation emails can only be sent in `pending_confirmation` mode".into(),
+        ));
+    }
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let list = get_list(&mut tx, &options.list)
+        .await
+        .context("Failed to look up the list")?
+        .ok_or_else(|| ImportError::InvalidInput(format!("Unknown list: {}", options.list)))?;
+    tx.rollback()
+        .await
+        .context("Failed to end the transaction")?;
+
+    let (sender, mut rows) = mpsc::channel(ROW_BUFFER);
+    let reader = tokio::task::spawn_blocking(move || read_rows(input, sender));
+
+    let mut report = ImportReport::default();
+    while let Some(ParsedRow { line, row }) = rows.recv().await {
+        let row = match row {
+            Ok(row) => row,
+            Err(reason) => {
+                report.push(RowReport {
+                    line,
+                    email: None,
+                    outcome: RowOutcome::Invalid,
+                    reason: Some(reason),
+                });
+                continue;
+            }
+        };
+        let email = Some(row.email.clone());
+        let subscriber = match NewSubscriber::new(row.email, row.name) {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                report.push(RowReport {
+                    line,
+                    email,
+                    outcome: RowOutcome::Invalid,
+                    reason: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+        let (outcome, reason) = import_row(db_pool, &list, &subscriber, options).await?;
+        report.push(RowReport {
+            line,
+            email,
+            outcome,
+            reason,
+        });
+    }
+
+    reader
+        .await
+        .context("The CSV reader panicked")?
+        .map_err(|e| match e {
+            ReadError::MissingColumn(column) => {
+                ImportError::InvalidInput(format!("The CSV has no `{column}` column"))
+            }
+            ReadError::Csv(e) => {
+                ImportError::UnexpectedError(anyhow!(e).context("Failed to read the CSV"))
+            }
+        })?;
+
+    Ok(report)
+}
+
+async fn import_row(
+    db_pool: &PgPool,
+    list: &List,
+    subscriber: &NewSubscriber,
+    options: &ImportOptions,
+) -> Result<(RowOutcome, Option<String>), anyhow::Error> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to begin a transaction")?;
+    let Some(subscriber_id) = insert_subscriber(&mut tx, subscriber, options.mode)
+        .await
+        .context("Failed to insert an imported subscriber")?
+    else {
+        return Ok((
+            RowOutcome::Duplicate,
+            Some("A subscriber with this email address already exists".into()),
+        ));
+    };
+
+    match options.mode {
+        ImportMode::Confirmed => {
+            add_confirmed_membership(&mut tx, list.list_id, subscriber_id)
+                .await
+                .context("Failed to store the list membership")?;
+        }
+        ImportMode::PendingConfirmation => {
+            request_membership(&mut tx, list.list_id, subscriber_id)
+                .await
+                .context("Failed to store the list membership")?;
+            let subscription_token = generate_subscription_token();
+            store_token(&mut tx, subscriber_id, list.list_id, &subscription_token)
+                .await
+                .context("Failed to store subscription token")?;
+            if options.send_confirmations {
+                enqueue_confirmation_email(&mut tx, &subscription_token)
+                    .await
+                    .context("Failed to queue the confirmation email")?;
+            }
+        }
+    }
+    tx.commit().await.context("Failed to commit transaction")?;
+
+    Ok((RowOutcome::Accepted, None))
+}
+
+/// Inserts the subscriber unless their address is already taken, in which
+/// case it returns `None`.
+async fn insert_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    mode: ImportMode,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+
+    let result = sqlx::query!(
+        r#"
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (email) DO NOTHING
+        "#,
+        subscriber_id,
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        Utc::now(),
+        mode.as_str(),
+        generate_subscription_token(),
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok((result.rows_affected() == 1).then_some(subscriber_id))
+}
+
+async fn add_confirmed_membership(
+    tx: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO list_memberships (list_id, subscriber_id, status)
+    VALUES ($1, $2, 'confirmed')
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum ReadError {
+    MissingColumn(&'static str),
+    Csv(csv::Error),
+}
+
+/// Parses `input` and sends its rows down `sender`. Runs on a blocking
+/// thread: `input` may itself be waiting on the network.
+///
+/// Rows that cannot be parsed are sent as errors; only a missing column or
+/// a failure to read `input` at all stops the import.
+fn read_rows<R: Read>(input: R, sender: mpsc::Sender<ParsedRow>) -> Result<(), ReadError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(input);
+    let headers = reader.headers().map_err(ReadError::Csv)?.clone();
+    for column in ["email", "name"] {
+        if !headers.iter().any(|header| header == column) {
+            return Err(ReadError::MissingColumn(column));
+        }
+    }
+
+    let mut record = csv::StringRecord::new();
+    loop {
+        let mut line = reader.position().line();
+        let row = match reader.read_record(&mut record) {
+            Ok(false) => return Ok(()),
+            Ok(true) => {
+                // Blank lines are skipped, so the row may start further on.
+                line = record.position().map_or(line, |position| position.line());
+                record
+                    .deserialize::<CsvRow>(Some(&headers))
+                    .map_err(|e| e.to_string())
+            }
+            Err(e) if e.is_io_error() => return Err(ReadError::Csv(e)),
+            Err(e) => Err(e.to_string()),
+        };
+        if sender.blocking_send(ParsedRow { line, row }).is_err() {
+            // The import gave up on us.
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParsedRow, ReadError, read_rows};
+    use tokio::sync::mpsc;
+
+    fn read(csv: &'static str) -> (Result<(), ReadError>, Vec<ParsedRow>) {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let outcome = read_rows(csv.as_bytes(), sender);
+        let mut rows = vec![];
+        while let Ok(row) = receiver.try_recv() {
+            rows.push(row);
+        }
+        (outcome, rows)
+    }
+
+    #[test]
+    fn rows_are_read_by_column_name_with_their_line() {
+        let (outcome, rows) = read(
+            "name,plan,email\nUrsula,pro,ursula@example.com\n\"Le\nGuin\",,le.guin@example.com\n",
+        );
+
+        assert!(outcome.is_ok());
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|r| {
+                let row = r.row.unwrap();
+                (r.line, row.email, row.name)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (2, "ursula@example.com".into(), "Ursula".into()),
+                (3, "le.guin@example.com".into(), "Le\nGuin".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_missing_a_field_are_reported_and_skipped() {
+        let (outcome, rows) = read("email,name\nursula@example.com\nged@example.com,Ged\n");
+
+        assert!(outcome.is_ok());
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].row.is_err());
+        assert_eq!(rows[1].row.as_ref().unwrap().name, "Ged");
+    }
+
+    #[test]
+    fn a_csv_without_the_expected_columns_is_rejected() {
+        let (outcome, rows) = read("mail,name\nursula@example.com,Ursula\n");
+
+        assert!(matches!(outcome, Err(ReadError::MissingColumn("email"))));
+        assert!(rows.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! Email templates, loaded from disk when the app starts.
+//!
+//! Every template is compiled and rendered once against sample data before the
+//! server comes up, so a typo breaks the deploy rather than the next send.
+
+use crate::config::TemplatesConfig;
+use crate::plain_text::html_to_text;
+use anyhow::Context;
+use minijinja::{Environment, UndefinedBehavior};
+use minijinja_autoreload::AutoReloader;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// The templates the app knows how to render. The newsletter layout comes as
+/// an HTML and a plain text flavour; the confirmation email's plain text is
+/// generated from its HTML.
+const TEMPLATE_NAMES: [&str; 3] = ["confirmation.html", "newsletter.html", "newsletter.txt"];
+
+/// Sent when someone subscribes, asking them to confirm their address.
+#[derive(Serialize)]
+pub struct ConfirmationEmail<'a> {
+    pub name: &'a str,
+    pub list_name: &'a str,
+    pub confirmation_link: &'a str,
+    pub preferences_url: &'a str,
+}
+
+/// The layout wrapped around every issue, with its header and footer.
+#[derive(Serialize)]
+pub struct NewsletterEmail<'a> {
+    pub name: &'a str,
+    pub title: &'a str,
+    /// Rendered as is; the HTML layout does not escape it.
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+}
+
+/// Both bodies of an email.
+pub struct RenderedEmail {
+    pub html: String,
+    pub text: String,
+}
+
+pub struct Templates {
+    reloader: AutoReloader,
+}
+
+impl Templates {
+    /// Loads and checks every template in the configured directory. With
+    /// `hot_reload` on, edits are picked up on the next render.
+    pub fn load(config: &TemplatesConfig) -> Result<Self, anyhow::Error> {
+        let directory = PathBuf::from(&config.directory);
+        let hot_reload = config.hot_reload;
+        let reloader = AutoReloader::new(move |notifier| {
+            if hot_reload {
+                notifier.watch_path(&directory, true);
+            }
+            build_environment(&directory)
+        });
+        let templates = Self { reloader };
+        templates
+            .check()
+            .with_context(|| format!("Invalid templates in {}", config.directory))?;
+
+        Ok(templates)
+    }
+
+    pub fn render_confirmation(
+        &self,
+        context: &ConfirmationEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        let html = self.render("confirmation.html", context)?;
+        let text = html_to_text(&html)?;
+        Ok(RenderedEmail { html, text })
+    }
+
+    pub fn render_newsletter(
+        &self,
+        context: &NewsletterEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        Ok(RenderedEmail {
+            html: self.render("newsletter.html", context)?,
+            text: self.render("newsletter.txt", context)?,
+        })
+    }
+
+    fn render<S: Serialize>(&self, name: &str, context: &S) -> Result<String, anyhow::Error> {
+        let env = self.reloader.acquire_env()?;
+        let rendered = env
+            .get_template(name)?
+            .render(context)
+            .with_context(|| format!("Failed to render the {name} template"))?;
+        Ok(rendered)
+    }
+
+    /// Renders each template with sample data, which catches the mistakes
+    /// compilation alone lets through, such as a misspelt variable.
+    fn check(&self) -> Result<(), anyhow::Error> {
+        self.render_confirmation(&ConfirmationEmail {
+            name: "Ursula Le Guin",
+            list_name: "Newsletter",
+            confirmation_link: "https://example.com/subscriptions/confirm",
+            preferences_url: "https://example.com/preferences/token",
+        })?;
+        self.render_newsletter(&NewsletterEmail {
+            name: "Ursula Le Guin",
+            title: "Issue title",
+            html_content: "<p>Issue content</p>",
+            text_content: "Issue content",
+            unsubscribe_url: "https://example.com/subscriptions/unsubscribe",
+            preferences_url: "https://example.com/preferences/token",
+        })?;
+        Ok(())
+    }
+}
+
+fn build_environment(directory: &Path) -> Result<Environment<'static>, minijinja::Error> {
+    let mut env = Environment::new();
+    // A variable the context doesn't provide is an error, not a blank.
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    for name in TEMPLATE_NAMES {
+        let source = std::fs::read_to_string(directory.join(name)).map_err(|e| {
+            minijinja::Error::new(
+                minijinja::ErrorKind::TemplateNotFound,
+                format!("Failed to read {name}"),
+            )
+            .with_source(e)
+        })?;
+        env.add_template_owned(name, source)?;
+    }
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Templates;
+    use crate::config::TemplatesConfig;
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    fn config(directory: &std::path::Path) -> TemplatesConfig {
+        TemplatesConfig {
+            directory: directory.to_string_lossy().into_owned(),
+            hot_reload: false,
+        }
+    }
+
+    #[test]
+    fn the_shipped_templates_load() {
+        assert_ok!(Templates::load(&config("templates".as_ref())));
+    }
+
+    #[test]
+    fn a_template_referencing_an_unknown_variable_fails_to_load() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&directory).unwrap();
+        for entry in std::fs::read_dir("templates").unwrap() {
+            let path = entry.unwrap().path();
+            std::fs::copy(&path, directory.join(path.file_name().unwrap())).unwrap();
+        }
+        std::fs::write(directory.join("newsletter.txt"), "Hi {{ nmae }}!").unwrap();
+
+        let outcome = Templates::load(&config(&directory));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn the_html_layout_escapes_the_subscriber_name() {
+        let templates = Templates::load(&config("templates".as_ref())).unwrap();
+
+        let rendered = templates
+            .render_newsletter(&super::NewsletterEmail {
+                name: "<b>Ursula</b>",
+                title: "Title",
+                html_content: "<p>Content</p>",
+                text_content: "Content",
+                unsubscribe_url: "https://example.com/unsubscribe",
+                preferences_url: "https://example.com/preferences",
+            })
+            .unwrap();
+
+        assert!(rendered.html.contains("&lt;b&gt;Ursula&lt;&#x2f;b&gt;"));
+        assert!(rendered.html.contains("<p>Content</p>"));
+        assert!(rendered.text.contains("<b>Ursula</b>"));
+    }
+}
@@ -0,0 +1,259 @@
+//! Open and click tracking.
+//!
+//! Tracking URLs carry a token signed with the app's HMAC secret. A token
+//! names a delivery and, for clicks, the position of a link in the issue. It
+//! never carries a URL: redirects only lead to links stored with the issue, so
+//! the click endpoint can't be turned into an open redirect.
+
+use crate::config::TrackingConfig;
+use crate::signing;
+use regex::{Captures, Regex};
+use secrecy::SecretString;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+static RE_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(<a\b[^>]*?\bhref=")([^"]*)(")"#).unwrap());
+
+/// What an issue opted into when it was written.
+#[derive(Clone, Copy, Debug)]
+pub struct IssueTracking {
+    pub opens: bool,
+    pub clicks: bool,
+}
+
+/// What a valid tracking token stands for.
+#[derive(Debug, PartialEq)]
+pub struct TrackingToken {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub kind: TrackingKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TrackingKind {
+    Open,
+    Click { link_index: i32 },
+}
+
+pub struct Tracker {
+    config: TrackingConfig,
+    hmac_secret: SecretString,
+    base_url: String,
+}
+
+impl Tracker {
+    pub fn new(config: TrackingConfig, hmac_secret: SecretString, base_url: String) -> Self {
+        Self {
+            config,
+            hmac_secret,
+            base_url,
+        }
+    }
+
+    /// Rewrites the links of an issue and adds an open pixel, as far as both
+    /// the issue and the global configuration allow.
+    pub fn instrument(
+        &self,
+        html: &str,
+        tracking: IssueTracking,
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> String {
+        let mut html = html.to_owned();
+        if tracking.clicks && self.config.clicks {
+            let mut link_index = 0;
+            html = RE_LINK
+                .replace_all(&html, |captures: &Captures| {
+                    if !is_trackable(&captures[2]) {
+                        return captures[0].to_owned();
+                    }
+                    let token = self.sign(&TrackingToken {
+                        newsletter_issue_id,
+                        subscriber_id,
+                        kind: TrackingKind::Click { link_index },
+                    });
+                    link_index += 1;
+                    format!(
+                        "{}{}/t/c/{token}{}",
+                        &captures[1], self.base_url, &captures[3]
+                    )
+                })
+                .into_owned();
+        }
+        if tracking.opens && self.config.opens {
+            let token = self.sign(&TrackingToken {
+                newsletter_issue_id,
+                subscriber_id,
+                kind: TrackingKind::Open,
+            });
+            html.push_str(&format!(
+                r#"<img src="{}/t/o/{token}" width="1" height="1" alt="">"#,
+                self.base_url
+            ));
+        }
+        html
+    }
+
+    pub fn sign(&self, token: &TrackingToken) -> String {
+        signing::sign(&self.hmac_secret, encode(token))
+    }
+
+    /// Returns what the token stands for, or `None` if it wasn't signed by us.
+    pub fn verify(&self, token: &str) -> Option<TrackingToken> {
+        decode(&signing::verify(&self.hmac_secret, token)?)
+    }
+}
+
+/// The URLs click tracking would rewrite in `html`, in document order. The
+/// position of a URL in this list is its `link_index`.
+pub fn trackable_links(html: &str) -> Vec<String> {
+    RE_LINK
+        .captures_iter(html)
+        .map(|captures| captures[2].to_owned())
+        .filter(|href| is_trackable(href))
+        .map(|href| htmlescape::decode_html(&href).unwrap_or(href))
+        .collect()
+}
+
+/// Only web links are tracked. Merge tags such as `{{unsubscribe_url}}` are
+/// left alone: nobody should have to go through a redirect to leave.
+fn is_trackable(href: &str) -> bool {
+    (href.starts_with("http://") || href.starts_with("https://")) && !href.contains("{{")
+}
+
+fn encode(token: &TrackingToken) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(37);
+    bytes.push(match token.kind {
+        TrackingKind::Open => b'o',
+        TrackingKind::Click { .. } => b'c',
+    });
+    bytes.extend_from_slice(token.newsletter_issue_id.as_bytes());
+    bytes.extend_from_slice(token.subscriber_id.as_bytes());
+    if let TrackingKind::Click { link_index } = token.kind {
+        bytes.extend_from_slice(&link_index.to_be_bytes());
+    }
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Option<TrackingToken> {
+    let (&kind, rest) = bytes.split_first()?;
+    let newsletter_issue_id = Uuid::from_slice(rest.get(..16)?).ok()?;
+    let subscriber_id = Uuid::from_slice(rest.get(16..32)?).ok()?;
+    let kind = match (kind, rest.get(32..)?) {
+        (b'o', []) => TrackingKind::Open,
+        (b'c', link_index) => TrackingKind::Click {
+            link_index: i32::from_be_bytes(link_index.try_into().ok()?),
+        },
+        _ => return None,
+    };
+    Some(TrackingToken {
+        newsletter_issue_id,
+        subscriber_id,
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueTracking, Tracker, TrackingKind, TrackingToken, trackable_links};
+    use crate::config::TrackingConfig;
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    fn tracker(opens: bool, clicks: bool) -> Tracker {
+        Tracker::new(
+            TrackingConfig { opens, clicks },
+            SecretString::from("a-secret"),
+            "https://news.example.com".to_string(),
+        )
+    }
+
+    fn click_token(link_index: i32) -> TrackingToken {
+        TrackingToken {
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+            kind: TrackingKind::Click { link_index },
+        }
+    }
+
+    #[test]
+    fn signed_tokens_verify() {
+        let tracker = tracker(true, true);
+        let token = click_token(3);
+
+        let verified = tracker.verify(&tracker.sign(&token));
+
+        assert_eq!(verified, Some(token));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let tracker = tracker(true, true);
+        let mut signed = tracker.sign(&click_token(3)).into_bytes();
+        signed[5] = if signed[5] == b'A' { b'B' } else { b'A' };
+
+        assert_eq!(tracker.verify(std::str::from_utf8(&signed).unwrap()), None);
+        assert_eq!(tracker.verify("not-a-token"), None);
+        assert_eq!(tracker.verify(""), None);
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let other = Tracker::new(
+            TrackingConfig {
+                opens: true,
+                clicks: true,
+            },
+            SecretString::from("another-secret"),
+            "https://news.example.com".to_string(),
+        );
+
+        assert_eq!(
+            tracker(true, true).verify(&other.sign(&click_token(0))),
+            None
+        );
+    }
+
+    #[test]
+    fn only_web_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/a?x=1&amp;y=2">A</a>
+            <a href="mailto:hi@example.com">Mail</a>
+            <a href="{{unsubscribe_url}}">Leave</a>
+            <a href="http://example.com/b">B</a>"#;
+        let tracker = tracker(true, true);
+        let tracking = IssueTracking {
+            opens: false,
+            clicks: true,
+        };
+
+        let instrumented = tracker.instrument(html, tracking, Uuid::new_v4(), Uuid::new_v4());
+
+        assert_eq!(
+            instrumented
+                .matches("https://news.example.com/t/c/")
+                .count(),
+            2
+        );
+        assert!(instrumented.contains(r#"href="mailto:hi@example.com""#));
+        assert!(instrumented.contains(r#"href="{{unsubscribe_url}}""#));
+        assert_eq!(
+            trackable_links(html),
+            vec!["https://example.com/a?x=1&y=2", "http://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn the_global_switch_overrides_the_issue() {
+        let html = r#"<p><a href="https://example.com">A</a></p>"#;
+        let tracking = IssueTracking {
+            opens: true,
+            clicks: true,
+        };
+
+        let instrumented =
+            tracker(false, false).instrument(html, tracking, Uuid::new_v4(), Uuid::new_v4());
+
+        assert_eq!(instrumented, html);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::api::helpers::{TestApp, init};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Inserts a subscriber straight into the database, returning their id.
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, now(), $4, $1::uuid::text)
+        "#,
+        subscriber_id,
+        email,
+        name,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert the subscriber.");
+    subscriber_id
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_in_email_order_one_page_at_a_time() {
+    // Arrange
+    let app = init().await;
+    for email in ["c@example.com", "a@example.com", "b@example.com"] {
+        insert_subscriber(&app, email, "name", "confirmed").await;
+    }
+
+    // Act - Part 1 - First page
+    let response = app.get_admin_subscribers("limit=2").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let first: serde_json::Value = response.json().await.unwrap();
+
+    // Act - Part 2 - Follow the cursor
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let response = app
+        .get_admin_subscribers(&format!("limit=2&cursor={cursor}"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let second: serde_json::Value = response.json().await.unwrap();
+
+    // Assert
+    assert_eq!(emails(&first), vec!["a@example.com", "b@example.com"]);
+    assert_eq!(emails(&second), vec!["c@example.com"]);
+    assert!(second["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_searched_by_prefix() {
+    // Arrange
+    let app = init().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(&app, "ged@example.com", "Sparrowhawk", "confirmed").await;
+    insert_subscriber(&app, "tenar@example.com", "Tenar", "unsubscribed").await;
+    insert_subscriber(&app, "u_x@example.com", "Someone", "confirmed").await;
+
+    // Act
+    let confirmed = app.get_admin_subscribers("status=confirmed").await;
+    let by_name = app.get_admin_subscribers("search=sparrow").await;
+    let by_email = app.get_admin_subscribers("search=U").await;
+    let with_wildcard = app.get_admin_subscribers("search=u_").await;
+
+    // Assert
+    let page = |response: reqwest::Response| async move {
+        assert_eq!(response.status().as_u16(), 200);
+        response.json::<serde_json::Value>().await.unwrap()
+    };
+    assert_eq!(
+        emails(&page(confirmed).await),
+        vec!["ged@example.com", "u_x@example.com", "ursula@example.com"]
+    );
+    assert_eq!(emails(&page(by_name).await), vec!["ged@example.com"]);
+    assert_eq!(
+        emails(&page(by_email).await),
+        vec!["u_x@example.com", "ursula@example.com"]
+    );
+    assert_eq!(emails(&page(with_wildcard).await), vec!["u_x@example.com"]);
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected_with_a_json_400() {
+    // Arrange
+    let app = init().await;
+    let test_cases = [
+        ("status=active", "unknown status"),
+        ("search=%3Cscript%3E", "a search with forbidden characters"),
+        ("cursor=%21%21", "a cursor that is not base64"),
+        ("limit=0", "a limit of 0"),
+        ("limit=lots", "a limit that is not a number"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = app.get_admin_subscribers(query).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the query was {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "validation_error");
+        assert!(body["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn a_subscriber_is_returned_with_their_lists_and_tags() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app
+        .create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.put_subscriber_tag(subscriber_id, "beta").await;
+
+    // Act
+    let response = app.get_admin_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["id"], subscriber_id.to_string());
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["name"], "le guin");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["digest_frequency"], "immediately");
+    assert_eq!(subscriber["lists"], serde_json::json!(["newsletter"]));
+    assert_eq!(subscriber["tags"], serde_json::json!(["beta"]));
+    assert_eq!(subscriber["attributes"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_a_json_404() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = Uuid::new_v4();
+
+    // Act
+    let responses = [
+        app.get_admin_subscriber(subscriber_id).await,
+        app.patch_admin_subscriber(subscriber_id, &serde_json::json!({"name": "Tenar"}))
+            .await,
+        app.delete_admin_subscriber(subscriber_id).await,
+    ];
+
+    // Assert
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 404);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "not_found");
+    }
+}
+
+#[tokio::test]
+async fn updates_rename_and_unsubscribe_and_are_recorded() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app
+        .create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let response = app
+        .patch_admin_subscriber(
+            subscriber_id,
+            &serde_json::json!({"name": "Ursula K. Le Guin", "status": "unsubscribed"}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["name"], "Ursula K. Le Guin");
+    assert_eq!(subscriber["status"], "unsubscribed");
+    assert_eq!(subscriber["lists"], serde_json::json!([]));
+
+    let changes =
+        sqlx::query!("SELECT field, old_value, new_value FROM preference_changes ORDER BY field")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    let changes: Vec<_> = changes
+        .into_iter()
+        .map(|r| (r.field, r.old_value.unwrap(), r.new_value.unwrap()))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (
+                "name".to_string(),
+                "le guin".to_string(),
+                "Ursula K. Le Guin".to_string()
+            ),
+            (
+                "status".to_string(),
+                "confirmed".to_string(),
+                "unsubscribed".to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_updates_are_rejected_with_a_json_400() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "name", "confirmed").await;
+    let test_cases = [
+        (serde_json::json!({"name": "<script>"}), "an invalid name"),
+        (serde_json::json!({"name": " "}), "a blank name"),
+        (serde_json::json!({"status": "active"}), "an unknown status"),
+        (serde_json::json!({}), "nothing to update"),
+        (
+            serde_json::json!({"name": 42}),
+            "a name that is not a string",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.patch_admin_subscriber(subscriber_id, &body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the update was {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "validation_error");
+    }
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "name");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_everything_about_them() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app
+        .create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.put_subscriber_tag(subscriber_id, "beta").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {"html": "<p>Newsletter body as HTML</p>"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let soft_bounce: serde_json::Value =
+        serde_json::from_str(include_str!("../fixtures/postmark/soft_bounce.json")).unwrap();
+    app.post_email_webhook(&soft_bounce)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app.delete_admin_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app.get_admin_subscriber(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM deliveries) AS "deliveries!",
+            (SELECT COUNT(*) FROM email_events) AS "email_events!",
+            (SELECT COUNT(*) FROM list_memberships) AS "memberships!",
+            (SELECT COUNT(*) FROM subscriber_tags) AS "tags!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.deliveries, 0);
+    assert_eq!(remaining.email_events, 0);
+    assert_eq!(remaining.memberships, 0);
+    assert_eq!(remaining.tags, 0);
+    assert_eq!(remaining.tokens, 0);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_keeps_their_preference_changes_without_names() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = insert_subscriber(&app, "a@example.com", "name", "confirmed").await;
+    app.patch_admin_subscriber(
+        subscriber_id,
+        &serde_json::json!({"name": "new name", "status": "unsubscribed"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = app.delete_admin_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let changes = sqlx::query!(
+        r#"
+        SELECT field, old_value, new_value FROM preference_changes
+        WHERE subscriber_id = $1
+        ORDER BY field
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].field, "name");
+    assert_eq!(changes[0].old_value, None);
+    assert_eq!(changes[0].new_value, None);
+    assert_eq!(changes[1].field, "status");
+    assert_eq!(changes[1].old_value.as_deref(), Some("confirmed"));
+    assert_eq!(changes[1].new_value.as_deref(), Some("unsubscribed"));
+}
+
+#[tokio::test]
+async fn the_admin_api_requires_authorization() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/api/subscribers", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "unauthorized");
+}
@@ -1,16 +1,31 @@
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version, password_hash::SaltString};
 use newsletter::{
-    config::{AppBaseUrl, DbConfig},
-    email_client::EmailClient,
-    startup::serve,
+    config::{
+        AppBaseUrl, DbConfig, EmailTransportKind, RetryConfig, TemplatesConfig, TrackingConfig,
+        WebhookConfig,
+    },
+    confirmation_email_worker,
+    email_client::{EmailTransport, build_transport},
+    issue_delivery_worker::{ExecutionOutcome, try_execute_task},
+    issue_scheduler::publish_due_issues,
+    preferences::PreferenceLinks,
+    startup::{AppState, serve},
+    templates::Templates,
+    tracking::Tracker,
 };
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
-use std::{env, sync::LazyLock};
+use std::{
+    env,
+    sync::{Arc, LazyLock},
+};
 use testcontainers::{ImageExt, runners::AsyncRunner};
 use testcontainers_modules::postgres;
 use tokio::{net::TcpListener, task::JoinHandle};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
-use wiremock::MockServer;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 static TRACING: LazyLock<()> = LazyLock::new(|| {
     let log_tests = env::var("LOG_TESTS").unwrap_or_default() == "true";
@@ -31,15 +46,87 @@ pub struct TestApp {
     pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub webhook_credentials: WebhookConfig,
+    pub templates: Arc<Templates>,
+    pub tracker: Arc<Tracker>,
+    pub preference_links: Arc<PreferenceLinks>,
     _server: JoinHandle<()>,
 }
 
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(15000, 2, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted = confirmation_email_worker::try_execute_task(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &self.templates,
+            &self.preference_links,
+            &self.address,
+        )
+        .await
+        .unwrap()
+        {}
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.templates,
+                &self.tracker,
+                &self.preference_links,
+                &self.address,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/subscriptions", &self.address))
@@ -53,12 +140,370 @@ impl TestApp {
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_newsletters(&self, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/newsletters?{}", &self.address, query))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_draft(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters/drafts", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_draft(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!(
+                "{}/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_draft(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!(
+                "{}/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preview(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/newsletters/{}/preview",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_schedule(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/{}/schedule",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_test_send(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/{}/test",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Runs one pass of the scheduler, returning how many issues went out.
+    pub async fn publish_due_issues(&self) -> usize {
+        publish_due_issues(&self.db_pool).await.unwrap()
+    }
+
+    pub async fn post_resume_newsletter(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/{}/resume",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_subscriber_tag(&self, subscriber_id: Uuid, tag: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!(
+                "{}/admin/api/subscribers/{}/tags/{}",
+                &self.address, subscriber_id, tag
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_subscriber_tag(&self, subscriber_id: Uuid, tag: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!(
+                "{}/admin/api/subscribers/{}/tags/{}",
+                &self.address, subscriber_id, tag
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_tags(&self, subscriber_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/api/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_subscriber_attributes(
+        &self,
+        subscriber_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!(
+                "{}/admin/api/subscribers/{}/attributes",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/api/subscribers?{}", &self.address, query))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/api/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_admin_subscriber(
+        &self,
+        subscriber_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!(
+                "{}/admin/api/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!(
+                "{}/admin/api/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_import(&self, query: &str, csv: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/api/subscribers/import?{}",
+                &self.address, query
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Content-Type", "text/csv")
+            .body(csv.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email", &self.address))
+            .basic_auth(
+                &self.webhook_credentials.username,
+                Some(self.webhook_credentials.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn login(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -66,6 +511,7 @@ impl TestApp {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| l.as_str().contains("/subscriptions/confirm"))
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -76,10 +522,70 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        // Links in the HTML body are entity-encoded, as in any attribute.
+        let html = get_link(&htmlescape::decode_html(body["HtmlBody"].as_str().unwrap()).unwrap());
         let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
+
+    /// Extracts the link advertised in the `List-Unsubscribe` header.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .expect("No List-Unsubscribe header");
+        let value = header["Value"].as_str().unwrap();
+        let raw_link = value.trim_start_matches('<').trim_end_matches('>');
+        reqwest::Url::parse(raw_link).unwrap()
+    }
+
+    /// Extracts the link to the preference center from the plain text body.
+    pub fn get_preferences_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .find(|l| l.as_str().contains("/preferences/"))
+            .expect("No link to the preference center");
+        reqwest::Url::parse(link.as_str()).unwrap()
+    }
+
+    /// Subscribes `email` as "le guin" and confirms it, returning the
+    /// subscriber's id. The confirmation email stays in `received_requests`.
+    pub async fn create_confirmed_subscriber(&self, email: &str) -> Uuid {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        reqwest::get(self.get_confirmation_links(email_request).html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to fetch the confirmed subscriber.")
+            .id
+    }
 }
 
 pub async fn init() -> TestApp {
@@ -106,25 +612,61 @@ pub async fn init() -> TestApp {
 
     let email_server = MockServer::start().await;
     let email_config = newsletter::config::EmailConfig {
+        transport: EmailTransportKind::Postmark,
         base_url: AppBaseUrl(email_server.uri()),
         sender_email: "user@example.com".to_string(),
         authorization_token: SecretString::from("test_token"),
         timeout_milliseconds: 2000,
+        retry: RetryConfig {
+            max_attempts: 3,
+            base_delay_milliseconds: 10,
+            max_delay_milliseconds: 100,
+            jitter: true,
+        },
+        smtp: None,
+        file: None,
+        test_subject_prefix: "[TEST]".to_string(),
+        webhook: WebhookConfig {
+            username: Uuid::new_v4().to_string(),
+            password: SecretString::from(Uuid::new_v4().to_string()),
+        },
     };
 
-    let sender_email = email_config.sender().unwrap();
-    let timeout = email_config.timeout();
-    let email_client = EmailClient::new(
-        email_config.base_url.0.clone(),
-        sender_email,
-        email_config.authorization_token,
-        timeout,
+    let email_client = build_transport(&email_config).unwrap();
+    let templates = Arc::new(
+        Templates::load(&TemplatesConfig {
+            directory: "templates".to_string(),
+            hot_reload: false,
+        })
+        .unwrap(),
     );
 
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let port = listener.local_addr().unwrap().port();
+    let address = format!("http://127.0.0.1:{}", port);
+
+    let hmac_secret = SecretString::from(Uuid::new_v4().to_string().repeat(2));
+    let tracker = Arc::new(Tracker::new(
+        TrackingConfig {
+            opens: true,
+            clicks: true,
+        },
+        hmac_secret.clone(),
+        address.clone(),
+    ));
+    let preference_links = Arc::new(PreferenceLinks::new(hmac_secret.clone(), address.clone()));
 
-    let server_future = serve(listener, pool.clone(), email_client, email_config.base_url)
+    let app_state = AppState {
+        db_pool: pool.clone(),
+        email_client: email_client.clone(),
+        base_url: email_config.base_url,
+        webhook_credentials: email_config.webhook.clone(),
+        test_subject_prefix: email_config.test_subject_prefix.clone(),
+        templates: templates.clone(),
+        tracker: tracker.clone(),
+        preference_links: preference_links.clone(),
+    };
+    let server_future = serve(listener, app_state, hmac_secret)
         .await
         .unwrap()
         .into_future();
@@ -135,11 +677,32 @@ pub async fn init() -> TestApp {
         }
     });
 
+    let test_user = TestUser::generate();
+    test_user.store(&pool).await;
+
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
     TestApp {
-        address: format!("http://127.0.0.1:{}", port),
+        address,
         port,
         db_pool: pool,
         email_server,
+        test_user,
+        api_client,
+        email_client,
+        webhook_credentials: email_config.webhook,
+        templates,
+        tracker,
+        preference_links,
         _server: handle,
     }
 }
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
@@ -0,0 +1,247 @@
+use crate::api::helpers::{TestApp, init};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) {
+    sqlx::query!(
+        "INSERT INTO lists (list_id, slug, name) VALUES ($1, $2, $3)",
+        Uuid::new_v4(),
+        slug,
+        name,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to create the list.");
+}
+
+/// Subscribes `email` to the list and follows the confirmation link.
+async fn create_confirmed_member(app: &TestApp, email: &str, list: &str) {
+    let body = format!("name=le%20guin&email={email}&list={list}");
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn membership_statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships AS m
+        INNER JOIN lists AS l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+fn issue_for(lists: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "lists": lists,
+    })
+}
+
+#[tokio::test]
+async fn subscribing_to_a_named_list_adds_a_pending_membership() {
+    // Arrange
+    let app = init().await;
+    create_list(&app, "beta", "Beta testers").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=beta".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![("beta".to_string(), "pending_confirmation".to_string())]
+    );
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().contains("Beta testers"));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_returns_a_400() {
+    // Arrange
+    let app = init().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=nope".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(membership_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn confirmation_only_applies_to_the_list_it_was_sent_for() {
+    // Arrange
+    let app = init().await;
+    create_list(&app, "beta", "Beta testers").await;
+    create_confirmed_member(&app, "ursula_le_guin%40gmail.com", "newsletter").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=beta".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            ("beta".to_string(), "pending_confirmation".to_string()),
+            ("newsletter".to_string(), "confirmed".to_string()),
+        ]
+    );
+    let email_request = app.email_server.received_requests().await.unwrap().pop();
+    let confirmation_link = app.get_confirmation_links(&email_request.unwrap()).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            ("beta".to_string(), "confirmed".to_string()),
+            ("newsletter".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_members_of_their_lists() {
+    // Arrange
+    let app = init().await;
+    create_list(&app, "beta", "Beta testers").await;
+    create_confirmed_member(&app, "newsletter_reader%40gmail.com", "newsletter").await;
+    create_confirmed_member(&app, "beta_tester%40gmail.com", "beta").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(issue_for(serde_json::json!(["beta"])))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM deliveries AS d
+        INNER JOIN subscriptions AS s ON s.id = d.subscriber_id
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].email, "beta_tester@gmail.com");
+}
+
+#[tokio::test]
+async fn members_of_several_target_lists_get_an_issue_once() {
+    // Arrange
+    let app = init().await;
+    create_list(&app, "beta", "Beta testers").await;
+    create_confirmed_member(&app, "ursula_le_guin%40gmail.com", "newsletter").await;
+    create_confirmed_member(&app, "ursula_le_guin%40gmail.com", "beta").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(issue_for(serde_json::json!(["newsletter", "beta"])))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn publishing_to_unknown_lists_returns_a_400() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app
+        .post_newsletters(issue_for(serde_json::json!(["newsletter", "nope"])))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("nope"));
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
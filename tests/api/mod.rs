@@ -1,5 +1,19 @@
+mod admin_dashboard;
+mod admin_subscribers;
+mod change_password;
 mod health;
 mod helpers;
+mod lists;
+mod login;
+mod newsletter_drafts;
+mod newsletter_issues;
+mod newsletter_test_sends;
 mod newsletters;
+mod preferences;
+mod subscriber_import;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
@@ -0,0 +1,313 @@
+use crate::api::helpers::{TestApp, init};
+use chrono::{TimeDelta, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn draft_body(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app.post_draft(&draft_body("Draft title")).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(draft["status"], "draft");
+    draft["id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn create_published_issue(app: &TestApp) -> Uuid {
+    app.post_newsletters(draft_body("Published title"))
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues WHERE status = 'published'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    create_draft(&app).await;
+
+    // Assert
+    assert_eq!(app.publish_due_issues().await, 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app.put_draft(id, &draft_body("A better title")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(draft["title"], "A better title");
+    assert!(
+        draft["slug"]
+            .as_str()
+            .unwrap()
+            .starts_with("a-better-title-")
+    );
+}
+
+#[tokio::test]
+async fn drafts_with_unknown_merge_tags_are_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+    let mut body = draft_body("Newsletter title");
+    body["content"]["text"] = "Hi {{subscriber.nickname}}".into();
+
+    // Act
+    let created = app.post_draft(&body).await;
+    let edited = app.put_draft(id, &body).await;
+
+    // Assert
+    assert_eq!(created.status().as_u16(), 400);
+    assert_eq!(edited.status().as_u16(), 400);
+    assert!(
+        edited
+            .text()
+            .await
+            .unwrap()
+            .contains("{{subscriber.nickname}}")
+    );
+}
+
+#[tokio::test]
+async fn saving_a_draft_warns_about_images_without_alt_text() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+    let body = serde_json::json!({
+        "title": "Draft title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Look:</p><img src=\"https://example.com/cat.png\">",
+        }
+    });
+    let expected = serde_json::json!(["The image https://example.com/cat.png has no alt text."]);
+
+    // Act
+    let created = app.post_draft(&body).await;
+    let updated = app.put_draft(id, &body).await;
+
+    // Assert
+    assert_eq!(created.status().as_u16(), 201);
+    let created: serde_json::Value = created.json().await.unwrap();
+    assert_eq!(created["warnings"], expected);
+    assert_eq!(updated.status().as_u16(), 200);
+    let updated: serde_json::Value = updated.json().await.unwrap();
+    assert_eq!(updated["warnings"], expected);
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app.delete_draft(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(app.get_newsletter(id).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_preview_renders_the_issue_as_subscribers_see_it() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app.get_preview(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = htmlescape::decode_html(&response.text().await.unwrap()).unwrap();
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_due() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    let id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Schedule for later
+    let send_at = Utc::now() + TimeDelta::hours(1);
+    let response = app
+        .post_schedule(id, &serde_json::json!({ "send_at": send_at }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+
+    // Act - Part 2 - Nothing is due yet
+    assert_eq!(app.publish_due_issues().await, 0);
+
+    // Act - Part 3 - Time passes
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - INTERVAL '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(app.publish_due_issues().await, 1);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue: serde_json::Value = app.get_newsletter(id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "published");
+    assert!(issue["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_not_delivered() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    let id = create_draft(&app).await;
+    app.post_schedule(id, &serde_json::json!({ "send_at": Utc::now() }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_cancel(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert_eq!(issue["send_at"], serde_json::Value::Null);
+    assert_eq!(app.publish_due_issues().await, 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn only_scheduled_issues_can_be_cancelled() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app.post_cancel(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn published_issues_can_no_longer_be_changed() {
+    // Arrange
+    let app = init().await;
+    let id = create_published_issue(&app).await;
+
+    // Act
+    let edit = app.put_draft(id, &draft_body("Too late")).await;
+    let delete = app.delete_draft(id).await;
+    let schedule = app
+        .post_schedule(id, &serde_json::json!({ "send_at": Utc::now() }))
+        .await;
+    let cancel = app.post_cancel(id).await;
+
+    // Assert
+    assert_eq!(edit.status().as_u16(), 409);
+    assert_eq!(delete.status().as_u16(), 409);
+    assert_eq!(schedule.status().as_u16(), 409);
+    assert_eq!(cancel.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn drafts_cannot_be_resumed() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app.post_resume_newsletter(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn drafts_are_not_in_the_public_archive() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+    let issue: serde_json::Value = app.get_newsletter(id).await.json().await.unwrap();
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/archive/{}",
+        &app.address,
+        issue["slug"].as_str().unwrap()
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn creating_a_draft_requires_authorization() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters/drafts", &app.address))
+        .json(&draft_body("Draft title"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,183 @@
+use crate::api::helpers::{TestApp, init};
+use uuid::Uuid;
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+async fn list_issues(app: &TestApp, query: &str) -> serde_json::Value {
+    app.get_newsletters(query)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_are_stored_with_their_author() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    publish_issue(&app, "Issue #1: Hello, world").await;
+
+    // Assert
+    let page = list_issues(&app, "").await;
+    assert_eq!(page["total"], 1);
+    let issue = &page["issues"][0];
+    assert_eq!(issue["title"], "Issue #1: Hello, world");
+    assert_eq!(issue["author"], app.test_user.username.as_str());
+    assert!(
+        issue["slug"]
+            .as_str()
+            .unwrap()
+            .starts_with("issue-1-hello-world-")
+    );
+    assert!(issue["created_at"].is_string());
+    assert!(issue["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn issues_are_listed_most_recent_first_one_page_at_a_time() {
+    // Arrange
+    let app = init().await;
+    for n in 1..=3 {
+        publish_issue(&app, &format!("Issue {n}")).await;
+    }
+
+    // Act
+    let first_page = list_issues(&app, "page=1&per_page=2").await;
+    let second_page = list_issues(&app, "page=2&per_page=2").await;
+
+    // Assert
+    let titles = |page: &serde_json::Value| -> Vec<String> {
+        page["issues"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["title"].as_str().unwrap().to_owned())
+            .collect()
+    };
+    assert_eq!(titles(&first_page), ["Issue 3", "Issue 2"]);
+    assert_eq!(titles(&second_page), ["Issue 1"]);
+    assert_eq!(first_page["total"], 3);
+    assert_eq!(second_page["page"], 2);
+}
+
+#[tokio::test]
+async fn listing_issues_with_invalid_pagination_is_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let test_cases = [
+        ("page=0", "page zero"),
+        (
+            "page=9223372036854775807",
+            "a page past the end of i64 offsets",
+        ),
+        ("per_page=0", "empty pages"),
+        ("per_page=1000", "oversized pages"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = app.get_newsletters(query).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn listing_issues_requires_authorization() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/newsletters", &app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_single_issue_is_returned_with_its_content() {
+    // Arrange
+    let app = init().await;
+    publish_issue(&app, "Newsletter title").await;
+    let page = list_issues(&app, "").await;
+    let id: Uuid = page["issues"][0]["id"].as_str().unwrap().parse().unwrap();
+
+    // Act
+    let response = app.get_newsletter(id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["title"], "Newsletter title");
+    assert_eq!(issue["content"]["html"], "<p>Newsletter body as HTML</p>");
+    assert_eq!(issue["content"]["text"], "Newsletter body as plain text");
+}
+
+#[tokio::test]
+async fn an_unknown_issue_returns_a_404() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app.get_newsletter(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_archive_renders_published_issues_without_authentication() {
+    // Arrange
+    let app = init().await;
+    publish_issue(&app, "Newsletter <title>").await;
+    let page = list_issues(&app, "").await;
+    let slug = page["issues"][0]["slug"].as_str().unwrap();
+
+    // Act
+    let response = reqwest::get(format!("{}/archive/{}", &app.address, slug))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Newsletter &lt;title&gt;</h1>"));
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn the_archive_returns_a_404_for_unknown_slugs() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/archive/not-an-issue", &app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
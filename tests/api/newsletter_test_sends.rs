@@ -0,0 +1,167 @@
+use crate::api::helpers::{TestApp, init};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "Draft title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    let draft: serde_json::Value = response.json().await.unwrap();
+    draft["id"].as_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_sends_go_to_every_recipient_with_a_marked_subject() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_test_send(
+            id,
+            &serde_json::json!({
+                "recipients": ["editor@example.com", "seed@example.com"]
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<_> = email_requests
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            assert_eq!(body["Subject"], "[TEST] Draft title");
+            assert!(
+                body["HtmlBody"]
+                    .as_str()
+                    .unwrap()
+                    .contains("<p>Newsletter body as HTML</p>")
+            );
+            // Rendered like a real send, footer and headers included.
+            app.get_unsubscribe_link(r);
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(recipients, ["editor@example.com", "seed@example.com"]);
+}
+
+#[tokio::test]
+async fn test_sends_are_not_recorded_as_deliveries() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_test_send(
+        id,
+        &serde_json::json!({ "recipients": ["editor@example.com"] }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let n_deliveries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM deliveries"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_deliveries, 0);
+    let issue: serde_json::Value = app.get_newsletter(id).await.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+}
+
+#[tokio::test]
+async fn test_sends_with_invalid_recipients_are_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let test_cases = [
+        (serde_json::json!({ "recipients": [] }), "no recipients"),
+        (
+            serde_json::json!({ "recipients": ["editor@example.com", "not-an-email"] }),
+            "an invalid address",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_test_send(id, &body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_400_lists_the_invalid_recipients() {
+    // Arrange
+    let app = init().await;
+    let id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_test_send(
+            id,
+            &serde_json::json!({ "recipients": ["not-an-email", "editor@example.com"] }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("not-an-email"));
+    assert!(!body.contains("editor@example.com"));
+}
+
+#[tokio::test]
+async fn test_sends_of_an_unknown_issue_return_a_404() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app
+        .post_test_send(
+            Uuid::new_v4(),
+            &serde_json::json!({ "recipients": ["editor@example.com"] }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,9 +1,10 @@
 use crate::api::helpers::{ConfirmationLinks, TestApp, init};
+use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let body = format!("name=le%20guin&email={}%40gmail.com", Uuid::new_v4());
 
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
@@ -12,7 +13,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -59,7 +60,8 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
     // The mock checks on Drop that no newsletter email was sent.
 }
 
@@ -87,7 +89,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
     // The mock checks on Drop that no newsletter email was sent.
 }
 
@@ -125,3 +128,653 @@ async fn newsletters_returns_422_for_invalid_data() {
         );
     }
 }
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn non_existing_user_is_rejected() {
+    // Arrange
+    let app = init().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_password_is_rejected() {
+    // Arrange
+    let app = init().await;
+    let username = &app.test_user.username;
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(app.test_user.password, password);
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Publish the newsletter
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Act - Part 2 - Retry the same request
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submissions_are_handled_gracefully() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - Submit two newsletter requests concurrently
+    let response1 = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 =
+        app.post_newsletters_with_idempotency_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_for_an_invalid_idempotency_key() {
+    // Arrange
+    let app = init().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    // Act
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_request_body, &"a".repeat(50))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn published_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the newsletter issue.")
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn successful_deliveries_are_recorded_with_the_provider_message_id() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK",
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery =
+        sqlx::query!("SELECT status, n_attempts, last_error, provider_message_id FROM deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch the delivery.");
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 1);
+    assert_eq!(delivery.last_error, None);
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_recorded_with_the_error() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status, n_attempts, last_error FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.");
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, 1);
+    assert!(delivery.last_error.is_some());
+}
+
+#[tokio::test]
+async fn deliveries_that_cannot_be_prepared_are_recorded_as_failed() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    // Make fetching the issue fail on every attempt.
+    sqlx::query("ALTER TABLE newsletter_issues RENAME COLUMN html_content TO html")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status, n_attempts, last_error FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.");
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, 1);
+    assert!(delivery.last_error.is_some());
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn the_oldest_delivery_task_is_sent_first() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    // Age the task a worker would not otherwise pick first.
+    let oldest = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET enqueued_at = enqueued_at - interval '1 hour'
+        WHERE subscriber_id <> (SELECT subscriber_id FROM issue_delivery_queue LIMIT 1)
+        RETURNING (SELECT email FROM subscriptions WHERE id = subscriber_id) AS "email!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // The first two requests are the confirmation emails.
+    let first_issue = &app.email_server.received_requests().await.unwrap()[2];
+    let body: serde_json::Value = serde_json::from_slice(&first_issue.body).unwrap();
+    assert_eq!(body["To"], oldest.email);
+}
+
+#[tokio::test]
+async fn issues_are_held_for_subscribers_on_a_digest_until_the_period_is_over() {
+    for (frequency, period_in_days) in [("daily", 1), ("weekly", 7)] {
+        // Arrange
+        let app = init().await;
+        create_confirmed_subscriber(&app).await;
+        sqlx::query!("UPDATE subscriptions SET digest_frequency = $1", frequency)
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        let held_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .named(format!("Nothing before the {frequency} digest is due"))
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.post_newsletters(newsletter_request_body())
+            .await
+            .error_for_status()
+            .unwrap();
+        app.dispatch_all_pending_emails().await;
+        drop(held_guard);
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&app.email_server)
+            .await;
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_queue
+            SET enqueued_at = enqueued_at - make_interval(days => $1)
+            "#,
+            period_in_days,
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+        // Act
+        app.dispatch_all_pending_emails().await;
+
+        // Assert
+        // The mock verifies on Drop that the issue went out once the period was over.
+    }
+}
+
+#[tokio::test]
+async fn resuming_an_issue_only_delivers_to_subscribers_who_did_not_get_it() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    let first_attempt_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let failing_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+    drop(first_attempt_guard);
+    drop(failing_guard);
+    let newsletter_issue_id = published_issue_id(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_resume_newsletter(newsletter_issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    let deliveries = sqlx::query!("SELECT status, n_attempts FROM deliveries ORDER BY n_attempts")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch deliveries.");
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d.status == "sent"));
+    assert_eq!(deliveries[0].n_attempts, 1);
+    assert_eq!(deliveries[1].n_attempts, 2);
+}
+
+#[tokio::test]
+async fn resuming_an_unknown_issue_returns_a_404() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app.post_resume_newsletter(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn resuming_an_issue_requires_authorization() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/newsletters/{}/resume",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_each_subscriber() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Hi {{subscriber.name}}! Read it online: {{ archive_url }}",
+            "html": "<p>Hi {{subscriber.name}}! <a href=\"{{unsubscribe_url}}\">Leave</a></p>",
+        }
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link(&email_request);
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(text.contains("Hi le guin! Read it online: "));
+    assert!(text.contains("/archive/newsletter-title-"));
+    assert!(html.contains(&format!(
+        "<p>Hi le guin! <a href=\"{}\" rel=\"noopener noreferrer\">Leave</a></p>",
+        unsubscribe_link
+    )));
+    assert!(!text.contains("{{") && !html.contains("{{"));
+}
+
+#[tokio::test]
+async fn unknown_merge_tags_are_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi {{subscriber.name}}, your code is {{coupon}}",
+                "html": "<p>Hi {{ subscriber.first_name }}, your code is {{coupon}}</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("{{ subscriber.first_name }}"));
+    assert!(body.contains("{{coupon}}"));
+    assert!(!body.contains("{{subscriber.name}}"));
+    let n_issues = sqlx::query_scalar!("SELECT COUNT(*) FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, Some(0));
+}
+
+#[tokio::test]
+async fn a_missing_plain_text_body_is_generated_from_the_html() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": "<h1>News</h1><p>Read <a href=\"https://example.com/post\">the post</a>.</p>",
+            }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    assert!(text.contains("# News\n\nRead [the post][1].\n\n[1]: https://example.com/post"));
+}
+
+#[tokio::test]
+async fn published_html_is_sanitized_and_its_styles_inlined() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<style>p { color: red; }</style><script>alert(1)</script><p>Body</p>",
+        }
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.contains(r#"<p style="color: red;">Body</p>"#));
+    assert!(!html.contains("<script>"));
+    assert!(!html.contains("alert(1)"));
+}
+
+#[tokio::test]
+async fn the_publish_response_warns_about_images_without_alt_text() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Look:</p><img src=\"https://example.com/cat.png\">",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["warnings"],
+        serde_json::json!(["The image https://example.com/cat.png has no alt text."])
+    );
+}
@@ -0,0 +1,289 @@
+use crate::api::helpers::{TestApp, init};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribes and confirms a subscriber, returning the link to their
+/// preference center from the confirmation email.
+async fn create_confirmed_subscriber(app: &TestApp) -> reqwest::Url {
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_preferences_link(&email_request)
+}
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) {
+    sqlx::query!(
+        "INSERT INTO lists (list_id, slug, name) VALUES ($1, $2, $3)",
+        uuid::Uuid::new_v4(),
+        slug,
+        name,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to create the list.");
+}
+
+async fn post_preferences(link: &reqwest::Url, form: &[(&str, &str)]) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(link.clone())
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn preference_changes(app: &TestApp) -> Vec<(String, Option<String>, Option<String>)> {
+    sqlx::query!("SELECT field, old_value, new_value FROM preference_changes ORDER BY field")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.field, r.old_value, r.new_value))
+        .collect()
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preference_center() {
+    // Arrange
+    let app = init().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {"html": "<p>Newsletter body as HTML</p>"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app.email_server.received_requests().await.unwrap().pop();
+    let link = app.get_preferences_link(&email_request.unwrap());
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("ursula_le_guin@gmail.com"));
+    assert!(html.contains(r#"value="le guin""#));
+    assert!(html.contains(r#"value="newsletter" checked"#));
+}
+
+#[tokio::test]
+async fn invalid_preference_links_return_a_404() {
+    // Arrange
+    let app = init().await;
+    let link = create_confirmed_subscriber(&app).await;
+    let mut tampered = link.path().to_owned();
+    tampered.pop();
+    tampered.push(if link.path().ends_with('A') { 'B' } else { 'A' });
+
+    // Act
+    let responses = [
+        reqwest::get(format!("{}{}", app.address, tampered))
+            .await
+            .unwrap(),
+        reqwest::get(format!("{}/preferences/not-a-token", app.address))
+            .await
+            .unwrap(),
+    ];
+
+    // Assert
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn preferences_are_updated_and_every_change_is_recorded() {
+    // Arrange
+    let app = init().await;
+    create_list(&app, "beta", "Beta testers").await;
+    let link = create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &link,
+        &[
+            ("name", "Ursula K. Le Guin"),
+            ("digest_frequency", "weekly"),
+            ("list", "beta"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Your preferences have been saved.")
+    );
+    let saved = sqlx::query!("SELECT name, digest_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.digest_frequency, "weekly");
+
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(
+        preference_changes(&app).await,
+        vec![
+            (
+                "digest_frequency".into(),
+                some("immediately"),
+                some("weekly")
+            ),
+            ("list:beta".into(), None, some("confirmed")),
+            (
+                "list:newsletter".into(),
+                some("confirmed"),
+                some("unsubscribed")
+            ),
+            ("name".into(), some("le guin"), some("Ursula K. Le Guin")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let link = create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &link,
+        &[
+            ("name", "<script>"),
+            ("digest_frequency", "weekly"),
+            ("list", "newsletter"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT name, digest_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.digest_frequency, "immediately");
+    assert!(preference_changes(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn an_unknown_digest_frequency_is_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let link = create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &link,
+        &[("name", "le guin"), ("digest_frequency", "hourly")],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribing_from_everything_stops_all_issues() {
+    // Arrange
+    let app = init().await;
+    let link = create_confirmed_subscriber(&app).await;
+    let unsubscribe = link.join(&format!("{}/unsubscribe", link.path())).unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(unsubscribe)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(
+        preference_changes(&app).await,
+        vec![
+            (
+                "list:newsletter".into(),
+                some("confirmed"),
+                some("unsubscribed")
+            ),
+            ("status".into(), some("confirmed"), some("unsubscribed")),
+        ]
+    );
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {"html": "<p>Newsletter body as HTML</p>"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn picking_a_list_again_resubscribes() {
+    // Arrange
+    let app = init().await;
+    let link = create_confirmed_subscriber(&app).await;
+    let unsubscribe = link.join(&format!("{}/unsubscribe", link.path())).unwrap();
+    reqwest::Client::new()
+        .post(unsubscribe)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    post_preferences(
+        &link,
+        &[
+            ("name", "le guin"),
+            ("digest_frequency", "immediately"),
+            ("list", "newsletter"),
+        ],
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT s.status, m.status AS membership FROM subscriptions AS s \
+         INNER JOIN list_memberships AS m ON m.subscriber_id = s.id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(saved.membership, "confirmed");
+}
@@ -0,0 +1,228 @@
+use crate::api::helpers::init;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn a_confirmed_import_reports_what_happened_to_every_row() {
+    // Arrange
+    let app = init().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let csv = "\
+email,name,plan
+ged@example.com,Sparrowhawk,pro
+not-an-email,Tenar,free
+ursula_le_guin@gmail.com,le guin,free
+ged@example.com,Ged,pro
+tehanu@example.com
+";
+
+    // Act
+    let response = app.post_subscriber_import("mode=confirmed", csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["duplicates"], 2);
+    assert_eq!(report["invalid"], 2);
+    let rows: Vec<_> = report["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            assert_eq!(row["outcome"] != "accepted", row["reason"].is_string());
+            (
+                row["line"].as_u64().unwrap(),
+                row["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            (2, "accepted"),
+            (3, "invalid"),
+            (4, "duplicate"),
+            (5, "duplicate"),
+            (6, "invalid"),
+        ]
+    );
+
+    let imported = sqlx::query!(
+        r#"
+        SELECT s.name, s.status, m.status AS membership
+        FROM subscriptions AS s
+        INNER JOIN list_memberships AS m ON m.subscriber_id = s.id
+        WHERE s.email = 'ged@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(imported.name, "Sparrowhawk");
+    assert_eq!(imported.status, "confirmed");
+    assert_eq!(imported.membership, "confirmed");
+    let existing =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(existing.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_pending_import_can_queue_confirmation_emails() {
+    // Arrange
+    let app = init().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name\nged@example.com,Sparrowhawk\ntenar@example.com,Tenar\n";
+
+    // Act
+    let response = app
+        .post_subscriber_import("mode=pending_confirmation&send_confirmations=true", csv)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 2);
+    // The import only queues them.
+    assert!(
+        app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let statuses = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            ("ged@example.com".into(), "confirmed".into()),
+            ("tenar@example.com".into(), "pending_confirmation".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_pending_import_sends_nothing_unless_asked() {
+    // Arrange
+    let app = init().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriber_import(
+            "mode=pending_confirmation",
+            "email,name\nged@example.com,Sparrowhawk\n",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn invalid_imports_are_rejected_with_a_json_400() {
+    // Arrange
+    let app = init().await;
+    let csv = "email,name\nged@example.com,Sparrowhawk\n";
+    let test_cases = [
+        ("", csv, "no mode"),
+        ("mode=active", csv, "an unknown mode"),
+        ("mode=confirmed&list=unknown", csv, "an unknown list"),
+        (
+            "mode=confirmed&send_confirmations=true",
+            csv,
+            "confirmation emails for confirmed subscribers",
+        ),
+        (
+            "mode=confirmed",
+            "mail,name\nged@example.com,Sparrowhawk\n",
+            "no email column",
+        ),
+    ];
+
+    for (query, csv, description) in test_cases {
+        // Act
+        let response = app.post_subscriber_import(query, csv).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the import had {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "validation_error");
+    }
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn imports_require_authorization() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/admin/api/subscribers/import?mode=confirmed",
+            &app.address
+        ))
+        .body("email,name\nged@example.com,Sparrowhawk\n")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
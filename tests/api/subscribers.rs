@@ -0,0 +1,211 @@
+use crate::api::helpers::{TestApp, init};
+use uuid::Uuid;
+
+/// Publishes an issue to the given segment and returns who it was delivered to.
+async fn publish_to_segment(app: &TestApp, segment: &str) -> Vec<String> {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment": segment,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM deliveries AS d
+        INNER JOIN subscriptions AS s ON s.id = d.subscriber_id
+        ORDER BY s.email
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.email)
+    .collect()
+}
+
+#[tokio::test]
+async fn tags_can_be_added_listed_and_removed() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app.create_confirmed_subscriber("ursula@gmail.com").await;
+
+    // Act
+    for tag in ["Beta", "beta", "early-adopter"] {
+        let response = app.put_subscriber_tag(subscriber_id, tag).await;
+        assert_eq!(response.status().as_u16(), 204);
+    }
+    let response = app
+        .delete_subscriber_tag(subscriber_id, "early-adopter")
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // Assert
+    let response = app.get_subscriber_tags(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let tags: Vec<String> = response.json().await.unwrap();
+    assert_eq!(tags, vec!["beta"]);
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app.create_confirmed_subscriber("ursula@gmail.com").await;
+
+    // Act
+    let response = app.put_subscriber_tag(subscriber_id, "not%20a%20tag").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "validation_error");
+}
+
+#[tokio::test]
+async fn tagging_an_unknown_subscriber_returns_a_404() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app.put_subscriber_tag(Uuid::new_v4(), "beta").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "not_found");
+}
+
+#[tokio::test]
+async fn tags_require_authorization() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app.create_confirmed_subscriber("ursula@gmail.com").await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .put(format!(
+            "{}/admin/api/subscribers/{}/tags/beta",
+            &app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn attributes_are_merged_and_null_removes_them() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app.create_confirmed_subscriber("ursula@gmail.com").await;
+    app.patch_subscriber_attributes(
+        subscriber_id,
+        &serde_json::json!({"plan": "pro", "seats": 3, "company": "Earthsea"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = app
+        .patch_subscriber_attributes(
+            subscriber_id,
+            &serde_json::json!({"seats": 12, "company": null}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let attributes: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(attributes, serde_json::json!({"plan": "pro", "seats": 12}));
+}
+
+#[tokio::test]
+async fn attributes_must_be_a_json_object() {
+    // Arrange
+    let app = init().await;
+    let subscriber_id = app.create_confirmed_subscriber("ursula@gmail.com").await;
+
+    // Act
+    let response = app
+        .patch_subscriber_attributes(subscriber_id, &serde_json::json!(["plan"]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "validation_error");
+}
+
+#[tokio::test]
+async fn issues_only_go_to_subscribers_in_their_segment() {
+    // Arrange
+    let app = init().await;
+    let beta = app.create_confirmed_subscriber("beta@gmail.com").await;
+    let churned = app.create_confirmed_subscriber("churned@gmail.com").await;
+    app.create_confirmed_subscriber("untagged@gmail.com").await;
+    app.put_subscriber_tag(beta, "beta").await;
+    app.put_subscriber_tag(churned, "beta").await;
+    app.put_subscriber_tag(churned, "churned").await;
+
+    // Act
+    let recipients = publish_to_segment(
+        &app,
+        "tag:beta AND NOT tag:churned AND subscribed_at > 2025-01-01",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(recipients, vec!["beta@gmail.com"]);
+}
+
+#[tokio::test]
+async fn segments_can_compare_attributes() {
+    // Arrange
+    let app = init().await;
+    let pro = app.create_confirmed_subscriber("pro@gmail.com").await;
+    let big = app.create_confirmed_subscriber("big@gmail.com").await;
+    let small = app.create_confirmed_subscriber("small@gmail.com").await;
+    app.patch_subscriber_attributes(pro, &serde_json::json!({"plan": "pro"}))
+        .await;
+    app.patch_subscriber_attributes(big, &serde_json::json!({"seats": 50}))
+        .await;
+    app.patch_subscriber_attributes(small, &serde_json::json!({"seats": "lots"}))
+        .await;
+
+    // Act
+    let recipients =
+        publish_to_segment(&app, r#"attributes.plan = "pro" OR attributes.seats >= 10"#).await;
+
+    // Assert
+    assert_eq!(recipients, vec!["big@gmail.com", "pro@gmail.com"]);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected_with_a_400() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"html": "<p>Newsletter body as HTML</p>"},
+            "segment": "tag:beta AND",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("Invalid segment"));
+}
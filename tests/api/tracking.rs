@@ -0,0 +1,165 @@
+use crate::api::helpers::{TestApp, init};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publishes an issue to a single confirmed subscriber and returns the HTML
+/// body they received.
+async fn deliver_tracked_issue(app: &TestApp, opens: bool, clicks: bool) -> String {
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p><a href=\"https://example.com/post?id=1&amp;ref=news\">Read</a> \
+                     or <a href=\"{{unsubscribe_url}}\">leave</a></p>",
+        },
+        "tracking": { "opens": opens, "clicks": clicks },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+fn tracking_links(app: &TestApp, html: &str, kind: &str) -> Vec<String> {
+    let prefix = format!("{}/t/{}/", app.address, kind);
+    html.split('"')
+        .filter(|s| s.starts_with(&prefix))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[tokio::test]
+async fn clicks_are_recorded_and_redirected_to_the_original_link() {
+    // Arrange
+    let app = init().await;
+    let html = deliver_tracked_issue(&app, false, true).await;
+    let links = tracking_links(&app, &html, "c");
+    assert_eq!(links.len(), 1);
+
+    // Act
+    let response = app.api_client.get(&links[0]).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/post?id=1&ref=news"
+    );
+    let event = sqlx::query!("SELECT kind, link_index FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "click");
+    assert_eq!(event.link_index, Some(0));
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_is_never_tracked() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let html = deliver_tracked_issue(&app, false, true).await;
+
+    // Assert
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    assert!(!html.contains("https://example.com/post"));
+}
+
+#[tokio::test]
+async fn opens_are_recorded_through_the_pixel() {
+    // Arrange
+    let app = init().await;
+    let html = deliver_tracked_issue(&app, true, false).await;
+    let pixels = tracking_links(&app, &html, "o");
+    assert_eq!(pixels.len(), 1);
+
+    // Act
+    let response = app.api_client.get(&pixels[0]).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    let event = sqlx::query!("SELECT kind, link_index FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "open");
+    assert_eq!(event.link_index, None);
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_unless_they_opt_in() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let html = deliver_tracked_issue(&app, false, false).await;
+
+    // Assert
+    assert!(!html.contains("/t/"));
+    assert!(html.contains("https://example.com/post?id=1&amp;ref=news"));
+}
+
+#[tokio::test]
+async fn tampered_or_foreign_tokens_are_rejected_with_a_404() {
+    // Arrange
+    let app = init().await;
+    let html = deliver_tracked_issue(&app, true, true).await;
+    let link = tracking_links(&app, &html, "c").pop().unwrap();
+    let pixel = tracking_links(&app, &html, "o").pop().unwrap();
+    let click_token = link.rsplit('/').next().unwrap();
+    let mut tampered = click_token.to_owned().into_bytes();
+    tampered[10] = if tampered[10] == b'A' { b'B' } else { b'A' };
+    let tampered = String::from_utf8(tampered).unwrap();
+
+    let test_cases = vec![
+        (
+            format!("{}/t/c/{}", app.address, tampered),
+            "a tampered token",
+        ),
+        (
+            format!("{}/t/c/https%3A%2F%2Fevil.example.com", app.address),
+            "a URL instead of a token",
+        ),
+        // An open token is signed, but doesn't name a link.
+        (pixel.replace("/t/o/", "/t/c/"), "an open token"),
+    ];
+
+    for (url, description) in test_cases {
+        // Act
+        let response = app.api_client.get(&url).send().await.unwrap();
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            404,
+            "The click endpoint did not reject {}.",
+            description
+        );
+    }
+    let n_events = sqlx::query_scalar!("SELECT COUNT(*) FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, Some(0));
+}
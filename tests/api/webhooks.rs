@@ -0,0 +1,231 @@
+use crate::api::helpers::{TestApp, init};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn fixture(name: &str) -> serde_json::Value {
+    let raw = match name {
+        "hard_bounce" => include_str!("../fixtures/postmark/hard_bounce.json"),
+        "soft_bounce" => include_str!("../fixtures/postmark/soft_bounce.json"),
+        "spam_complaint" => include_str!("../fixtures/postmark/spam_complaint.json"),
+        _ => panic!("Unknown fixture {}", name),
+    };
+    serde_json::from_str(raw).unwrap()
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn webhook_requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email", &app.address))
+        .json(&fixture("hard_bounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="webhooks""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn webhook_requests_with_the_wrong_password_are_rejected() {
+    // Arrange
+    let app = init().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email", &app.address))
+        .basic_auth(&app.webhook_credentials.username, Some("wrong-password"))
+        .json(&fixture("hard_bounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let response = app.post_email_webhook(&fixture("hard_bounce")).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(subscriber_status(&app).await, "bounced");
+}
+
+#[tokio::test]
+async fn a_soft_bounce_leaves_the_subscriber_confirmed() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let response = app.post_email_webhook(&fixture("soft_bounce")).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let response = app.post_email_webhook(&fixture("spam_complaint")).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio::test]
+async fn every_event_is_stored_for_audit() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    for name in ["hard_bounce", "soft_bounce", "spam_complaint"] {
+        app.post_email_webhook(&fixture(name))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let events =
+        sqlx::query!("SELECT record_type, email, provider_message_id, payload FROM email_events ORDER BY received_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .expect("Failed to fetch email events.");
+    let record_types: Vec<_> = events.iter().map(|e| e.record_type.as_str()).collect();
+    assert_eq!(record_types, ["Bounce", "Bounce", "SpamComplaint"]);
+    assert!(events.iter().all(|e| {
+        e.email.as_deref() == Some("ursula_le_guin@gmail.com")
+            && e.provider_message_id.as_deref() == Some("883953f4-6105-42a2-a16a-77a8eac79483")
+    }));
+    assert_eq!(events[0].payload, fixture("hard_bounce"));
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_matching_delivery_as_bounced() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "ErrorCode": 0,
+            "Message": "OK",
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    app.post_email_webhook(&fixture("hard_bounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.");
+    assert_eq!(delivery.status, "bounced");
+}
+
+#[tokio::test]
+async fn bounced_subscribers_do_not_receive_newsletters() {
+    // Arrange
+    let app = init().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    app.post_email_webhook(&fixture("hard_bounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_hard_bounce_invalidates_outstanding_confirmation_links() {
+    // Arrange
+    let app = init().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.post_email_webhook(&fixture("hard_bounce")).await;
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+}